@@ -0,0 +1,748 @@
+//! Admonitions, the `admonish` [preprocessor](crate::preprocessors): ` ```admonish <kind> <title> ` code blocks become callouts (notes, warnings...), with their body rendered as Markdown.
+//!
+//! GitHub's alerts (blockquotes starting with `[!NOTE]`, `[!WARNING]`...) are rendered the same way, so pages look right on GitHub too.
+//!
+//! Besides the built-in kinds, projects can declare their own in `[admonitions.<kind>]`, see [`stylesheet`].
+
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::iter::Peekable;
+use std::path::Path;
+use std::vec;
+
+use handlebars::Handlebars;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Parser, Tag};
+use serde_json::json;
+
+use crate::config::{AdmonitionConfig, PageConfig};
+use crate::error::Context;
+use crate::preprocessors::{PageContext, Preprocessor};
+use crate::site::{markdown_options, templates_as_html};
+use crate::{templates, Error, Result};
+
+/// Kinds styled by the built-in stylesheet (`admonitions.scss`), each one with its aliases.
+pub const KINDS: &[&[&str]] = &[
+    &["note"],
+    &["abstract", "summary", "tldr"],
+    &["info", "todo"],
+    &["tip", "hint", "important"],
+    &["success", "check", "done"],
+    &["question", "help", "faq"],
+    &["warning", "caution", "attention"],
+    &["failure", "fail", "missing"],
+    &["danger", "error"],
+    &["bug"],
+    &["example"],
+    &["quote", "cite"],
+];
+
+/// Colour of notes, used by declared kinds without a `color`
+const NOTE_COLOR: &str = "#448aff";
+
+/// Passed to the template as `content`, where the admonition's body goes.
+const CONTENT: &str = "<!--cuteness:admonition-content-->";
+
+/// An admonition, parsed from a code block's info string:
+///
+/// ```text
+/// admonish [options] [kind] [options] [title]
+/// ```
+///
+/// Options are `collapsible` (a `<details>` element), `open` (collapsible and initially open) and `class=<class>` (added to the admonition's classes). The first word that isn't an option is the kind, the second one starts the title: options can't be put after it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Admonition {
+    /// Lowercased, `note` if it's missing
+    pub kind: String,
+    pub title: Option<String>,
+    pub collapsible: bool,
+    pub open: bool,
+    /// Extra classes, separated by spaces
+    pub class: String,
+}
+
+impl Admonition {
+    /// Parses the info string of a fenced code block, `None` if it isn't an admonition.
+    pub fn parse(info: &str) -> Option<Self> {
+        let mut words = info.split_whitespace();
+        if words.next() != Some("admonish") {
+            return None;
+        }
+
+        let mut kind = None;
+        let mut collapsible = false;
+        let mut open = false;
+        let mut classes = Vec::new();
+        let mut title = Vec::new();
+
+        for word in words {
+            if !title.is_empty() {
+                title.push(word);
+                continue;
+            }
+            match word {
+                "collapsible" => collapsible = true,
+                "open" => {
+                    collapsible = true;
+                    open = true;
+                }
+                _ => {
+                    if let Some(class) = word.strip_prefix("class=") {
+                        classes.extend(class.split(',').filter(|class| !class.is_empty()));
+                    } else if kind.is_none() {
+                        kind = Some(word);
+                    } else {
+                        title.push(word);
+                    }
+                }
+            }
+        }
+
+        Some(Self {
+            kind: kind.map_or_else(|| "note".to_string(), str::to_lowercase),
+            title: (!title.is_empty()).then(|| title.join(" ")),
+            collapsible,
+            open,
+            class: classes.join(" "),
+        })
+    }
+
+    /// Renders the `admonish.html.hbs` template, returns the HTML that goes before and after the admonition's body.
+    ///
+    /// Admonitions without a title get their kind's one (from `kinds`, the `[admonitions]` table), or the kind capitalized.
+    pub fn render(
+        &self,
+        reg: &Handlebars,
+        kinds: &BTreeMap<String, AdmonitionConfig>,
+    ) -> Result<(String, String)> {
+        let title = self
+            .title
+            .clone()
+            .or_else(|| kinds.get(&self.kind)?.title.clone())
+            .unwrap_or_else(|| {
+                let mut chars = self.kind.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            });
+
+        // The registry doesn't escape anything, and the title is used in an attribute too.
+        let data = json!({
+            "kind": self.kind,
+            "title": handlebars::html_escape(&title),
+            "collapsible": self.collapsible,
+            "open": self.open,
+            "class": handlebars::html_escape(&self.class),
+            "content": CONTENT,
+        });
+
+        // Builds register the project's `admonish.html.hbs` (see `Templates`), fall back to the global one otherwise.
+        let html = if reg.has_template("admonish") {
+            reg.render("admonish", &data)
+        } else {
+            reg.render_template(&templates::template("admonish.html.hbs")?, &data)
+        }
+        .map_err(|e| Error::template("admonish.html.hbs", e))?;
+
+        // Templates written for older versions leave a paragraph open for the body instead of using `content`.
+        Ok(match html.split_once(CONTENT) {
+            Some((start, end)) => (start.to_string(), end.to_string()),
+            None => (html, "</p></div>".to_string()),
+        })
+    }
+}
+
+/// Turns ` ```admonish ` blocks and GitHub alerts (`> [!NOTE]`) into admonitions, using the `admonish.html.hbs` template.
+pub struct Admonish;
+
+impl Preprocessor for Admonish {
+    fn name(&self) -> &str {
+        "admonish"
+    }
+
+    fn run<'a>(
+        &self,
+        page: &PageContext<'_>,
+        _front_matter: &mut PageConfig,
+        events: Vec<Event<'a>>,
+    ) -> Result<Vec<Event<'a>>> {
+        admonitions(
+            events,
+            page.registry,
+            &page.config.admonitions,
+            &mut |message| page.warn(message),
+        )
+    }
+}
+
+/// Replaces admonition code blocks and alerts by their HTML, the body of code blocks is parsed as Markdown (so admonitions can be nested, with longer fences).
+///
+/// Unknown kinds are reported with `warn` and shown as notes.
+fn admonitions<'a>(
+    events: Vec<Event<'a>>,
+    reg: &Handlebars,
+    kinds: &BTreeMap<String, AdmonitionConfig>,
+    warn: &mut impl FnMut(String),
+) -> Result<Vec<Event<'a>>> {
+    let mut result = Vec::with_capacity(events.len());
+    // How every open blockquote ends: the closing HTML of an alert, `None` for plain blockquotes
+    let mut blockquotes: Vec<Option<String>> = Vec::new();
+    let mut events = events.into_iter().peekable();
+
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::BlockQuote) => {
+                let (alert, content) = alert(&mut events, kinds);
+                match alert {
+                    Some(alert) => {
+                        let (start, end) = alert.render(reg, kinds)?;
+                        result.push(Event::Html(start.into()));
+                        blockquotes.push(Some(end));
+                    }
+                    None => {
+                        result.push(event);
+                        blockquotes.push(None);
+                    }
+                }
+                result.extend(content);
+                continue;
+            }
+            Event::End(Tag::BlockQuote) => {
+                match blockquotes.pop().flatten() {
+                    Some(end) => result.push(Event::Html(end.into())),
+                    None => result.push(event),
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let admonition = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => Admonition::parse(info),
+            _ => None,
+        };
+        let Some(mut admonition) = admonition else {
+            result.push(event);
+            continue;
+        };
+        if builtin(&admonition.kind).is_none() && !kinds.contains_key(&admonition.kind) {
+            warn(format!(
+                "unknown admonition kind `{0}`, shown as a note (declare it in `[admonitions.{0}]`)",
+                admonition.kind
+            ));
+            admonition.kind = "note".to_string();
+        }
+
+        // Code blocks only contain text, usually a line per event
+        let mut lines = Vec::new();
+        for event in events.by_ref() {
+            match event {
+                Event::Text(text) => lines.push(text),
+                Event::End(_) => break,
+                _ => {}
+            }
+        }
+        let body = lines.concat();
+
+        let (start, end) = admonition.render(reg, kinds)?;
+        result.push(Event::Html(start.into()));
+        // The body stays as events, so headings get IDs and are in the table of contents.
+        let nested = Parser::new_ext(&body, markdown_options())
+            .map(|event| owned(event, &lines))
+            .collect();
+        let nested = templates_as_html(nested);
+        result.extend(admonitions(nested, reg, kinds, warn)?);
+        result.push(Event::Html(end.into()));
+    }
+
+    Ok(result)
+}
+
+/// Reads the start of a blockquote, returns the alert it is (if its first line is `[!<kind>]`, with a built-in or declared kind) and the events read that are part of its content.
+fn alert<'a>(
+    events: &mut Peekable<vec::IntoIter<Event<'a>>>,
+    kinds: &BTreeMap<String, AdmonitionConfig>,
+) -> (Option<Admonition>, Vec<Event<'a>>) {
+    let mut content = Vec::new();
+    if !matches!(events.peek(), Some(Event::Start(Tag::Paragraph))) {
+        return (None, content);
+    }
+    content.extend(events.next());
+
+    // `[!NOTE]` can be split in several text events
+    let mut text = String::new();
+    while let Some(Event::Text(part)) = events.peek() {
+        text.push_str(part);
+        events.next();
+    }
+
+    let kind = text
+        .strip_prefix("[!")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .map(str::to_lowercase)
+        .filter(|kind| builtin(kind).is_some() || kinds.contains_key(kind))
+        .filter(|_| matches!(events.peek(), Some(Event::SoftBreak | Event::End(_))));
+    let Some(kind) = kind else {
+        if !text.is_empty() {
+            content.push(Event::Text(text.into()));
+        }
+        return (None, content);
+    };
+
+    // The marker is alone on its line, drop the line break or the paragraph if there's nothing else in it.
+    if let Some(Event::End(_)) = events.next() {
+        content.clear();
+    }
+
+    let alert = Admonition {
+        kind,
+        title: None,
+        collapsible: false,
+        open: false,
+        class: String::new(),
+    };
+    (Some(alert), content)
+}
+
+/// Generates the styles of the kinds declared in `[admonitions]` (only the ones with a `color` or an `icon` if they're built-in).
+///
+/// Icons that aren't inline SVG are read from files, relative to `root`.
+pub fn stylesheet(root: &Path, kinds: &BTreeMap<String, AdmonitionConfig>) -> Result<String> {
+    let mut css = String::from("/* Generated by cuteness from `[admonitions]` */\n");
+
+    for (kind, config) in kinds {
+        let builtin = builtin(kind);
+        if builtin.is_some() && config.color.is_none() && config.icon.is_none() {
+            continue;
+        }
+
+        let color = config.color.as_deref().unwrap_or(NOTE_COLOR);
+        let icon =
+            match config.icon.as_deref().map(str::trim) {
+                Some(svg) if svg.starts_with('<') => Some(svg.to_string()),
+                Some(path) => Some(read_to_string(root.join(path)).with_context(|| {
+                    format!("Couldn't read icon `{path}` of admonition `{kind}`")
+                })?),
+                None => None,
+            };
+
+        // Same rules as the built-in kinds (see `admonitions.scss`)
+        if let Some(icon) = icon {
+            css.push_str(&format!(
+                ":root {{ --md-admonition-icon--{kind}: url(\"data:image/svg+xml;charset=utf-8,{}\"); }}\n",
+                data_url(&icon)
+            ));
+        }
+        let icon = match builtin {
+            _ if config.icon.is_some() => kind.as_str(),
+            Some(name) => name,
+            None => "note",
+        };
+        css.push_str(&format!(
+            ":is(.admonition).{kind} {{ border-color: {color}; }}
+.{kind} > :is(.admonition-title, summary) {{ background-color: color-mix(in srgb, {color} 10%, transparent); }}
+.{kind} > :is(.admonition-title, summary)::before {{
+  background-color: {color};
+  mask-image: var(--md-admonition-icon--{icon});
+  -webkit-mask-image: var(--md-admonition-icon--{icon});
+}}
+"
+        ));
+    }
+
+    Ok(css)
+}
+
+/// Name of a built-in kind (`warning` for `caution`), `None` if it isn't one.
+fn builtin(kind: &str) -> Option<&'static str> {
+    KINDS
+        .iter()
+        .find(|names| names.contains(&kind))
+        .map(|names| names[0])
+}
+
+/// Escapes an SVG to put it in a `data:` URL between double quotes.
+fn data_url(svg: &str) -> String {
+    svg.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('%', "%25")
+        .replace('#', "%23")
+        .replace('"', "'")
+}
+
+/// Copies an event parsed from a temporary string, the body of an admonition made of `lines`.
+///
+/// Headings' explicit attributes (`{#id .class}`) have to borrow from the page's source: they're taken from the lines of the code block, which do (except in nested admonitions, whose headings with attributes are turned into raw HTML).
+fn owned<'a>(event: Event<'_>, lines: &[CowStr<'a>]) -> Event<'a> {
+    match event {
+        Event::Start(Tag::Heading(level, id, classes)) => {
+            let id = match id {
+                Some(id) => borrow(lines, id).map(Some),
+                None => Some(None),
+            };
+            let borrowed: Option<Vec<&'a str>> =
+                classes.iter().map(|class| borrow(lines, class)).collect();
+            match id.zip(borrowed) {
+                Some((id, classes)) => Event::Start(Tag::Heading(level, id, classes)),
+                None => {
+                    let mut heading = String::new();
+                    html::push_html(
+                        &mut heading,
+                        std::iter::once(Event::Start(Tag::Heading(level, id.flatten(), classes))),
+                    );
+                    Event::Html(heading.into())
+                }
+            }
+        }
+        Event::Start(tag) => Event::Start(owned_tag(tag)),
+        Event::End(tag) => Event::End(owned_tag(tag)),
+        Event::Text(text) => Event::Text(owned_str(text)),
+        Event::Code(code) => Event::Code(owned_str(code)),
+        Event::Html(html) => Event::Html(owned_str(html)),
+        Event::FootnoteReference(name) => Event::FootnoteReference(owned_str(name)),
+        Event::SoftBreak => Event::SoftBreak,
+        Event::HardBreak => Event::HardBreak,
+        Event::Rule => Event::Rule,
+        Event::TaskListMarker(checked) => Event::TaskListMarker(checked),
+    }
+}
+
+/// Finds `s` in the lines borrowed from the page's source.
+fn borrow<'a>(lines: &[CowStr<'a>], s: &str) -> Option<&'a str> {
+    lines.iter().find_map(|line| match line {
+        CowStr::Borrowed(line) => line.find(s).map(|start| &line[start..start + s.len()]),
+        _ => None,
+    })
+}
+
+fn owned_tag<'a>(tag: Tag<'_>) -> Tag<'a> {
+    match tag {
+        Tag::Paragraph => Tag::Paragraph,
+        // The end of a heading doesn't need its attributes
+        Tag::Heading(level, ..) => Tag::Heading(level, None, Vec::new()),
+        Tag::BlockQuote => Tag::BlockQuote,
+        Tag::CodeBlock(CodeBlockKind::Indented) => Tag::CodeBlock(CodeBlockKind::Indented),
+        Tag::CodeBlock(CodeBlockKind::Fenced(info)) => {
+            Tag::CodeBlock(CodeBlockKind::Fenced(owned_str(info)))
+        }
+        Tag::List(start) => Tag::List(start),
+        Tag::Item => Tag::Item,
+        Tag::FootnoteDefinition(name) => Tag::FootnoteDefinition(owned_str(name)),
+        Tag::Table(alignments) => Tag::Table(alignments),
+        Tag::TableHead => Tag::TableHead,
+        Tag::TableRow => Tag::TableRow,
+        Tag::TableCell => Tag::TableCell,
+        Tag::Emphasis => Tag::Emphasis,
+        Tag::Strong => Tag::Strong,
+        Tag::Strikethrough => Tag::Strikethrough,
+        Tag::Link(kind, url, title) => Tag::Link(kind, owned_str(url), owned_str(title)),
+        Tag::Image(kind, url, title) => Tag::Image(kind, owned_str(url), owned_str(title)),
+    }
+}
+
+fn owned_str<'a>(s: CowStr<'_>) -> CowStr<'a> {
+    s.into_string().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(template: &str) -> Handlebars<'static> {
+        let mut reg = Handlebars::new();
+        reg.register_escape_fn(handlebars::no_escape);
+        reg.register_template_string("admonish", template).unwrap();
+        reg
+    }
+
+    /// Renders `markdown`, returns the HTML and the warnings.
+    fn render_with(
+        markdown: &str,
+        template: &str,
+        kinds: &BTreeMap<String, AdmonitionConfig>,
+    ) -> (String, Vec<String>) {
+        let mut warnings = Vec::new();
+        let events = Parser::new_ext(markdown, markdown_options()).collect();
+        let events = admonitions(events, &registry(template), kinds, &mut |message| {
+            warnings.push(message)
+        })
+        .unwrap();
+        let mut html = String::new();
+        html::push_html(&mut html, events.into_iter());
+        (html, warnings)
+    }
+
+    fn builtin_template() -> &'static str {
+        templates::TEMPLATES
+            .iter()
+            .find(|(name, _)| *name == "admonish.html.hbs")
+            .unwrap()
+            .1
+    }
+
+    fn render(markdown: &str) -> String {
+        render_with(markdown, builtin_template(), &BTreeMap::new()).0
+    }
+
+    fn kind(color: Option<&str>, icon: Option<&str>, title: Option<&str>) -> AdmonitionConfig {
+        AdmonitionConfig {
+            color: color.map(String::from),
+            icon: icon.map(String::from),
+            title: title.map(String::from),
+        }
+    }
+
+    #[test]
+    fn parse_kind_and_title() {
+        assert_eq!(
+            Admonition::parse("admonish warning Be careful"),
+            Some(Admonition {
+                kind: "warning".to_string(),
+                title: Some("Be careful".to_string()),
+                collapsible: false,
+                open: false,
+                class: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let admonition = Admonition::parse("admonish").unwrap();
+        assert_eq!(admonition.kind, "note");
+        assert_eq!(admonition.title, None);
+
+        let admonition = Admonition::parse("admonish Whatever title").unwrap();
+        assert_eq!(admonition.kind, "whatever");
+        assert_eq!(admonition.title.as_deref(), Some("title"));
+
+        // The title defaults to the kind
+        assert!(render("```admonish tip\n```").contains("<p>Tip</p>"));
+        assert!(render("```admonish\n```").contains("<p>Note</p>"));
+    }
+
+    #[test]
+    fn parse_not_an_admonition() {
+        assert_eq!(Admonition::parse(""), None);
+        assert_eq!(Admonition::parse("rust"), None);
+        assert_eq!(Admonition::parse("admonishment"), None);
+        assert_eq!(Admonition::parse("rust admonish"), None);
+    }
+
+    #[test]
+    fn parse_options() {
+        let admonition = Admonition::parse("admonish collapsible warning Title").unwrap();
+        assert!(admonition.collapsible);
+        assert!(!admonition.open);
+        assert_eq!(admonition.kind, "warning");
+        assert_eq!(admonition.title.as_deref(), Some("Title"));
+
+        let admonition =
+            Admonition::parse("admonish tip open class=big,wide class=red Hi").unwrap();
+        assert!(admonition.collapsible && admonition.open);
+        assert_eq!(admonition.kind, "tip");
+        assert_eq!(admonition.class, "big wide red");
+        assert_eq!(admonition.title.as_deref(), Some("Hi"));
+
+        // Options are only recognized before the title
+        let admonition = Admonition::parse("admonish info Keep it open").unwrap();
+        assert!(!admonition.open);
+        assert_eq!(admonition.title.as_deref(), Some("Keep it open"));
+    }
+
+    #[test]
+    fn body_is_markdown() {
+        let html = render(
+            "```admonish info Links\n* A [link](page.html)\n* `code`\n\n```rust\nfn main() {}\n```\n```",
+        );
+        assert!(html.contains(r#"<div class="admonition info">"#));
+        assert!(html.contains(r#"<li>A <a href="page.html">link</a></li>"#));
+        assert!(html.contains("<li><code>code</code></li>"));
+        assert!(html.contains(r#"<pre><code class="language-rust">fn main() {}"#));
+        assert_eq!(html.matches("<div").count(), html.matches("</div>").count());
+    }
+
+    #[test]
+    fn nested_admonitions() {
+        let html =
+            render("````admonish warning Outer\n```admonish tip Inner\n**Bold**\n```\n````\n");
+        assert!(html.contains(r#"<div class="admonition warning">"#));
+        assert!(html.contains(r#"<div class="admonition tip">"#));
+        assert!(html.contains("<strong>Bold</strong>"));
+        assert!(!html.contains("<pre>"));
+        assert_eq!(html.matches("<div").count(), html.matches("</div>").count());
+    }
+
+    #[test]
+    fn collapsible() {
+        let html = render("```admonish collapsible bug class=wide Details\nBody\n```");
+        assert!(html.contains(r#"<details class="admonition bug wide">"#));
+        assert!(html.contains("Details"));
+        assert!(render("```admonish note \"Quoted\"\n```").contains("&quot;Quoted&quot;"));
+        assert!(html.contains("<p>Body</p>"));
+        assert!(html.trim_end().ends_with("</details>"));
+
+        assert!(render("```admonish open bug\nBody\n```")
+            .contains(r#"<details class="admonition bug" open>"#));
+    }
+
+    #[test]
+    fn other_code_blocks_are_untouched() {
+        let html = render("```rust\n// admonish\n```\n\n```\nplain\n```");
+        assert!(html.contains(r#"<pre><code class="language-rust">// admonish"#));
+        assert!(html.contains("<pre><code>plain"));
+        assert!(!html.contains("admonition"));
+    }
+
+    #[test]
+    fn headings_keep_their_attributes() {
+        let html = render("```admonish\n## Heading {#custom .big}\n```");
+        assert!(html.contains(r#"<h2 id="custom" class="big">Heading</h2>"#));
+    }
+
+    #[test]
+    fn headings_stay_headings() {
+        let markdown = "```admonish\n## Heading {#custom .big}\n### Another\n```";
+        let events = Parser::new_ext(markdown, markdown_options()).collect();
+        let events = admonitions(
+            events,
+            &registry(builtin_template()),
+            &BTreeMap::new(),
+            &mut |_| {},
+        )
+        .unwrap();
+
+        let headings: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Start(Tag::Heading(level, id, classes)) => {
+                    Some((*level as usize, *id, classes.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            headings,
+            [(2, Some("custom"), vec!["big"]), (3, None, Vec::new())]
+        );
+    }
+
+    #[test]
+    fn legacy_template() {
+        let (html, _) = render_with(
+            "```admonish\n*Body*\n```",
+            r#"<div class="admonition {{kind}}"><p>{{title}}</p><p>"#,
+            &BTreeMap::new(),
+        );
+        assert!(html.contains(r#"<div class="admonition note"><p>Note</p><p>"#));
+        assert!(html.contains("<em>Body</em>"));
+        assert!(html.trim_end().ends_with("</p></div>"));
+    }
+
+    #[test]
+    fn unknown_kinds_warn() {
+        let (html, warnings) = render_with(
+            "```admonish security Careful\nBody\n```\n\n```admonish caution\n```",
+            builtin_template(),
+            &BTreeMap::new(),
+        );
+        assert!(html.contains(r#"<div class="admonition note">"#));
+        assert!(html.contains(r#"<div class="admonition caution">"#));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("`security`"));
+    }
+
+    #[test]
+    fn declared_kinds() {
+        let kinds = BTreeMap::from([
+            (
+                "security".to_string(),
+                kind(None, None, Some("Security notice")),
+            ),
+            ("tip".to_string(), kind(None, None, Some("Pro tip"))),
+        ]);
+        let (html, warnings) = render_with(
+            "```admonish security\n```\n\n```admonish security Custom\n```\n\n```admonish tip\n```",
+            builtin_template(),
+            &kinds,
+        );
+        assert!(warnings.is_empty());
+        assert!(html.contains(r#"<div class="admonition security">"#));
+        assert!(html.contains("<p>Security notice</p>"));
+        assert!(html.contains("<p>Custom</p>"));
+        assert!(html.contains("<p>Pro tip</p>"));
+    }
+
+    #[test]
+    fn stylesheet_for_declared_kinds() {
+        let kinds = BTreeMap::from([
+            (
+                "security".to_string(),
+                kind(
+                    Some("#e53935"),
+                    Some("<svg fill=\"#000\">\n  <path/>\n</svg>"),
+                    None,
+                ),
+            ),
+            ("beta".to_string(), kind(None, None, Some("Beta"))),
+            ("caution".to_string(), kind(Some("orange"), None, None)),
+            ("tip".to_string(), kind(None, None, Some("Pro tip"))),
+        ]);
+        let css = stylesheet(Path::new("."), &kinds).unwrap();
+
+        assert!(css.contains(
+            r#"--md-admonition-icon--security: url("data:image/svg+xml;charset=utf-8,<svg fill='%23000'> <path/> </svg>");"#
+        ));
+        assert!(css.contains(":is(.admonition).security { border-color: #e53935; }"));
+        assert!(css.contains("mask-image: var(--md-admonition-icon--security);"));
+        // Without a colour nor an icon, they look like notes
+        assert!(css.contains(":is(.admonition).beta { border-color: #448aff; }"));
+        assert!(css.contains("mask-image: var(--md-admonition-icon--note);"));
+        // Aliases use their built-in kind's icon
+        assert!(css.contains(":is(.admonition).caution { border-color: orange; }"));
+        assert!(css.contains("mask-image: var(--md-admonition-icon--warning);"));
+        // Built-in kinds with only a title don't need styles
+        assert!(!css.contains(".tip"));
+    }
+
+    #[test]
+    fn github_alerts() {
+        let html =
+            render("> [!WARNING]\n> Don't *panic*.\n> * Really.\n\n> [!tip]\n>\n> Lowercase");
+        assert!(html.contains(r#"<div class="admonition warning">"#));
+        assert!(html.contains("<p>Warning</p>"));
+        assert!(html.contains("<p>Don't <em>panic</em>.</p>"));
+        assert!(html.contains("<li>Really.</li>"));
+        assert!(html.contains(r#"<div class="admonition tip">"#));
+        assert!(html.contains("<p>Lowercase</p>"));
+        assert!(!html.contains("[!"));
+        assert!(!html.contains("blockquote"));
+        assert_eq!(html.matches("<div").count(), html.matches("</div>").count());
+    }
+
+    #[test]
+    fn github_alerts_nest() {
+        let html =
+            render("> [!NOTE]\n> > Quoted\n> > [!CAUTION]\n>\n> ```admonish bug\n> Inside\n> ```");
+        assert!(html.contains(r#"<div class="admonition note">"#));
+        // Only the first line of a blockquote can make it an alert
+        assert!(html.contains("<blockquote>\n<p>Quoted\n[!CAUTION]</p>\n</blockquote>"));
+        assert!(html.contains(r#"<div class="admonition bug">"#));
+        assert_eq!(html.matches("<div").count(), html.matches("</div>").count());
+    }
+
+    #[test]
+    fn not_github_alerts() {
+        for markdown in [
+            "> [!UNKNOWN]\n> Text",
+            "> [!NOTE] Text on the same line",
+            "> Text\n> [!NOTE]",
+            "> [NOTE]\n> Text",
+        ] {
+            let html = render(markdown);
+            assert!(html.contains("<blockquote>"), "{markdown}");
+            assert!(!html.contains("admonition"), "{markdown}");
+        }
+    }
+}
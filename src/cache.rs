@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, read_to_string};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{PageConfig, PluginConfig};
+use crate::error::Context;
+use crate::links::PageLinks;
+use crate::templates::Templates;
+use crate::{Error, Preprocessor, Result};
+
+/// Name of the build manifest, stored in the output directory.
+const CACHE_FILE: &str = ".cuteness-cache.json";
+
+/// Build manifest, it remembers what every page was rendered from so unchanged pages aren't parsed nor rendered again.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Cache {
+    /// Hash of everything every page depends on (see [`dependencies_hash`])
+    dependencies: String,
+    pages: BTreeMap<PathBuf, CachedPage>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedPage {
+    /// Hash of the source file (front matter included)
+    source: String,
+    /// Hashes of the files it includes (`{{#include}}`), by path relative to the root
+    #[serde(default)]
+    pub includes: BTreeMap<PathBuf, String>,
+    /// Parsed front matter, needed to generate the routes without parsing the page again.
+    pub config: PageConfig,
+    /// Needed to check links without parsing the page again.
+    pub links: PageLinks,
+    /// Warnings reported while rendering the page, shown again on every build until it's fixed.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl Cache {
+    /// Reads the manifest in `outdir`, a missing or unreadable manifest is just an empty cache.
+    pub fn load(outdir: &Path) -> Self {
+        read_to_string(outdir.join(CACHE_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, outdir: &Path) -> Result<()> {
+        fs::write(
+            outdir.join(CACHE_FILE),
+            serde_json::to_string(self).map_err(Error::Cache)?,
+        )
+        .with_context(|| {
+            format!(
+                "Couldn't write build cache `{}`",
+                outdir.join(CACHE_FILE).display()
+            )
+        })
+    }
+
+    /// Forgets every page if the shared dependencies changed since the last build.
+    pub fn set_dependencies(&mut self, dependencies: String) {
+        if self.dependencies != dependencies {
+            self.dependencies = dependencies;
+            self.pages.clear();
+        }
+    }
+
+    /// Gets the cached page for `path` if it was rendered from `source` and the files it includes (relative to `root`) didn't change.
+    pub fn get(&self, root: &Path, path: &Path, source: &str) -> Option<&CachedPage> {
+        self.pages.get(path).filter(|page| {
+            page.source == blake3::hash(source.as_bytes()).to_hex().as_str()
+                && page
+                    .includes
+                    .iter()
+                    .all(|(file, hash)| file_hash(&root.join(file)).as_ref() == Some(hash))
+        })
+    }
+
+    pub fn insert(
+        &mut self,
+        path: &Path,
+        source: &str,
+        config: PageConfig,
+        links: PageLinks,
+        warnings: Vec<String>,
+        includes: BTreeMap<PathBuf, String>,
+    ) {
+        self.pages.insert(
+            path.to_path_buf(),
+            CachedPage {
+                source: blake3::hash(source.as_bytes()).to_hex().to_string(),
+                includes,
+                config,
+                links,
+                warnings,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.pages.remove(path);
+    }
+}
+
+/// Hashes a file, `None` if it can't be read (e.g. it was deleted).
+pub fn file_hash(path: &Path) -> Option<String> {
+    fs::read(path)
+        .ok()
+        .map(|content| blake3::hash(&content).to_hex().to_string())
+}
+
+/// Hashes what every page of the project at `root` depends on: `cuteconfig.toml`, `SUMMARY.toml`, the templates, the preprocessors, the plugins' executables or modules and cuteness' own version.
+pub fn dependencies_hash(
+    root: &Path,
+    templates: &Templates,
+    preprocessors: &[Arc<dyn Preprocessor>],
+    plugins: &[PluginConfig],
+) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    for preprocessor in preprocessors {
+        hasher.update(preprocessor.name().as_bytes());
+    }
+
+    for file in ["cuteconfig.toml", "SUMMARY.toml"] {
+        hasher
+            .update(&fs::read(root.join(file)).with_context(|| format!("Couldn't read `{file}`"))?);
+    }
+
+    hasher.update(templates.hash()?.as_bytes());
+
+    // A plugin that can't be found doesn't start, there's nothing to cache then.
+    for hash in plugins
+        .iter()
+        .filter_map(|plugin| file_hash(&plugin_file(root, &plugin.command)?))
+    {
+        hasher.update(hash.as_bytes());
+    }
+
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Finds the file a plugin runs: paths (and `.wasm` modules) are relative to the project, bare command names are searched in `PATH`.
+fn plugin_file(root: &Path, command: &str) -> Option<PathBuf> {
+    if command.contains(['/', '\\']) || command.ends_with(".wasm") {
+        return Some(root.join(command));
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(command))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(title: &str) -> PageConfig {
+        toml::from_str(&format!("title = \"{title}\"")).unwrap()
+    }
+
+    #[test]
+    fn pages_are_kept_until_their_source_changes() {
+        let mut cache = Cache::default();
+        cache.set_dependencies("deps".to_string());
+        cache.insert(
+            Path::new("src/a.md"),
+            "A",
+            config("A"),
+            PageLinks::default(),
+            Vec::new(),
+            BTreeMap::new(),
+        );
+
+        assert_eq!(
+            cache
+                .get(Path::new("."), Path::new("src/a.md"), "A")
+                .unwrap()
+                .config
+                .title,
+            "A"
+        );
+        assert!(cache
+            .get(Path::new("."), Path::new("src/a.md"), "Changed")
+            .is_none());
+        assert!(cache
+            .get(Path::new("."), Path::new("src/b.md"), "A")
+            .is_none());
+
+        cache.remove(Path::new("src/a.md"));
+        assert!(cache
+            .get(Path::new("."), Path::new("src/a.md"), "A")
+            .is_none());
+    }
+
+    #[test]
+    fn changed_dependencies_forget_every_page() {
+        let mut cache = Cache::default();
+        cache.set_dependencies("deps".to_string());
+        cache.insert(
+            Path::new("src/a.md"),
+            "A",
+            config("A"),
+            PageLinks::default(),
+            Vec::new(),
+            BTreeMap::new(),
+        );
+
+        cache.set_dependencies("deps".to_string());
+        assert!(cache
+            .get(Path::new("."), Path::new("src/a.md"), "A")
+            .is_some());
+
+        cache.set_dependencies("other".to_string());
+        assert!(cache
+            .get(Path::new("."), Path::new("src/a.md"), "A")
+            .is_none());
+    }
+
+    #[test]
+    fn changed_includes_forget_their_page() {
+        let root =
+            std::env::temp_dir().join(format!("cuteness-cache-includes-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("code.rs"), "fn main() {}").unwrap();
+
+        let mut cache = Cache::default();
+        cache.insert(
+            Path::new("src/a.md"),
+            "A",
+            config("A"),
+            PageLinks::default(),
+            Vec::new(),
+            BTreeMap::from([(
+                PathBuf::from("code.rs"),
+                file_hash(&root.join("code.rs")).unwrap(),
+            )]),
+        );
+        assert!(cache.get(&root, Path::new("src/a.md"), "A").is_some());
+
+        fs::write(root.join("code.rs"), "fn main() { todo!() }").unwrap();
+        assert!(cache.get(&root, Path::new("src/a.md"), "A").is_none());
+        fs::remove_file(root.join("code.rs")).unwrap();
+        assert!(cache.get(&root, Path::new("src/a.md"), "A").is_none());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn saved_and_loaded() {
+        let outdir = std::env::temp_dir().join(format!("cuteness-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&outdir);
+        fs::create_dir_all(&outdir).unwrap();
+
+        // A missing (or broken) manifest is an empty cache
+        assert!(Cache::load(&outdir).pages.is_empty());
+        fs::write(outdir.join(CACHE_FILE), "{").unwrap();
+        assert!(Cache::load(&outdir).pages.is_empty());
+
+        let mut cache = Cache::default();
+        cache.set_dependencies("deps".to_string());
+        cache.insert(
+            Path::new("src/a.md"),
+            "A",
+            config("A"),
+            PageLinks::default(),
+            Vec::new(),
+            BTreeMap::new(),
+        );
+        cache.save(&outdir).unwrap();
+
+        let mut loaded = Cache::load(&outdir);
+        assert!(loaded
+            .get(Path::new("."), Path::new("src/a.md"), "A")
+            .is_some());
+        loaded.set_dependencies("deps".to_string());
+        assert!(loaded
+            .get(Path::new("."), Path::new("src/a.md"), "A")
+            .is_some());
+
+        fs::remove_dir_all(&outdir).unwrap();
+    }
+
+    #[test]
+    fn plugins_are_dependencies() {
+        let root =
+            std::env::temp_dir().join(format!("cuteness-cache-plugins-{}", std::process::id()));
+        fs::create_dir_all(root.join("plugins")).unwrap();
+        for file in ["cuteconfig.toml", "SUMMARY.toml"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join("plugins/upper.wasm"), "(module)").unwrap();
+
+        let templates = Templates::new(&root, None).unwrap();
+        let plugins = [PluginConfig {
+            command: "plugins/upper.wasm".to_string(),
+            args: Vec::new(),
+            timeout: None,
+        }];
+        let hash = || dependencies_hash(&root, &templates, &[], &plugins).unwrap();
+
+        let before = hash();
+        assert_eq!(hash(), before);
+        fs::write(root.join("plugins/upper.wasm"), "(module (memory 1))").unwrap();
+        assert_ne!(hash(), before);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn plugin_files() {
+        let root = Path::new("/project");
+        assert_eq!(
+            plugin_file(root, "tools/plugin"),
+            Some(root.join("tools/plugin"))
+        );
+        assert_eq!(
+            plugin_file(root, "plugin.wasm"),
+            Some(root.join("plugin.wasm"))
+        );
+        assert_eq!(plugin_file(root, "no-such-cuteness-plugin"), None);
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error as _;
+use std::fmt::Display;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use yaml_front_matter::YamlFrontMatter;
+
+use cuteness::config::PageConfig;
+use cuteness::params_in_path;
+use cuteness::preprocessors;
+use cuteness::site::{
+    check_collisions, page_route, read_config, read_page, read_summary, source_pages,
+};
+use cuteness::templates::Templates;
+use cuteness::Error;
+
+/// Problems found so far, reported all at once at the end.
+#[derive(Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn report(&mut self, file: impl AsRef<Path>, message: impl Display) {
+        let problem = format!("{}: {message}", file.as_ref().display());
+        // Problems of the configuration are found again with every page.
+        if !self.0.contains(&problem) {
+            self.0.push(problem);
+        }
+    }
+
+    /// Reports an error of the library, with its causes (they say what's wrong).
+    fn error(&mut self, file: impl AsRef<Path>, error: &Error) {
+        let mut message = error.to_string();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+        self.report(file, message);
+    }
+}
+
+/// Validates the project in the current directory without building it, see [`problems`].
+///
+/// Every problem is reported, failing if there's at least one.
+pub fn check() -> Result<()> {
+    let problems = problems(Path::new("."));
+
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+
+    for problem in &problems {
+        eprintln!("error: {problem}");
+    }
+
+    bail!("Found {} problem(s)", problems.len())
+}
+
+/// Finds what would make building the project at `root` fail (the configuration, the summary, every page's front matter and `{{#include}}`s, pages colliding...), and what wouldn't but is likely a mistake (missing styles, pages missing from the summary...).
+fn problems(root: &Path) -> Vec<String> {
+    let mut problems = Problems::default();
+
+    // * Configuration ===========================
+
+    let config = read_config(root)
+        .map_err(|e| problems.error("cuteconfig.toml", &e))
+        .ok();
+    let summary = read_summary(root)
+        .map_err(|e| problems.error("SUMMARY.toml", &e))
+        .ok();
+
+    let theme = config
+        .as_ref()
+        .and_then(|config| config.misc.theme.as_deref())
+        .map(Path::new);
+    let styles = match Templates::new(root, theme) {
+        Ok(templates) => templates.styles().unwrap_or_default(),
+        Err(e) => {
+            problems.error("templates", &e);
+            BTreeMap::new()
+        }
+    };
+
+    // * Pages ===================================
+
+    let paths = source_pages(root);
+    if let Err(e) = check_collisions(paths.iter().map(PathBuf::as_path)) {
+        problems.error("src", &e);
+    }
+
+    let available = preprocessors::builtin();
+    let mut routes = BTreeSet::new();
+
+    for path in &paths {
+        let is_pure = params_in_path(path).is_empty();
+        if is_pure {
+            routes.insert(page_route(path));
+        }
+
+        let content = match read_to_string(root.join(path)) {
+            Ok(content) => content,
+            Err(e) => {
+                problems.report(path, format!("couldn't read file: {e}"));
+                continue;
+            }
+        };
+
+        // Without a valid configuration, only the front matter can be checked.
+        let page = match &config {
+            Some(config) => read_page(root, path, &content, config, &available).map(|page| {
+                for warning in &page.warnings {
+                    problems.report(path, warning);
+                }
+                page.config
+            }),
+            None => YamlFrontMatter::parse::<PageConfig>(&content)
+                .map(|page| page.metadata)
+                .map_err(|e| Error::FrontMatter {
+                    path: path.clone(),
+                    message: e.to_string(),
+                }),
+        };
+        let page = match page {
+            Ok(page) => page,
+            Err(e) => {
+                problems.error(path, &e);
+                continue;
+            }
+        };
+
+        check_css(&mut problems, root, path, &page, &styles);
+
+        // Pages with parameters are dynamic, they can't be linked from the sidebar.
+        if is_pure
+            && summary
+                .as_ref()
+                .is_some_and(|summary| !summary.map.iter().any(|map| map.url == page_route(path)))
+        {
+            problems.report(path, "page isn't in `SUMMARY.toml`");
+        }
+    }
+
+    // * Summary =================================
+
+    if let Some(summary) = &summary {
+        for map in &summary.map {
+            if !routes.contains(&map.url) {
+                problems.report(
+                    "SUMMARY.toml",
+                    format!(
+                        "`{}` (\"{}\") doesn't match any file in `src`",
+                        map.url, map.title
+                    ),
+                );
+            }
+        }
+    }
+
+    problems.0
+}
+
+/// Every `additional_css` has to point to a style in `src/styles` (maybe compiled from Sass) or a built-in / theme style.
+fn check_css(
+    problems: &mut Problems,
+    root: &Path,
+    path: &Path,
+    page: &PageConfig,
+    styles: &BTreeMap<String, Vec<u8>>,
+) {
+    for css in page.additional_css.iter().flatten() {
+        // External stylesheets can't be checked.
+        if css.contains("://") || css.starts_with("//") {
+            continue;
+        }
+
+        let relative = css.trim_start_matches('/');
+        let source = root.join("src").join(relative);
+
+        let exists = source.is_file()
+            || ["sass", "scss"]
+                .iter()
+                .any(|ext| source.with_extension(ext).is_file())
+            || relative
+                .strip_prefix("styles/")
+                .is_some_and(|name| styles.contains_key(name));
+
+        if !exists {
+            problems.report(
+                path,
+                format!("`additional_css` entry `{css}` doesn't point to any file"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    /// Creates a project with `files` (path, content) in a new temporary directory.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cuteness-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let config = (
+            "cuteconfig.toml",
+            "[misc]\nhtml_lang = \"en\"\n\n[config]\n",
+        );
+        for (path, content) in std::iter::once(&config).chain(files) {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn valid_project() {
+        let root = project(
+            "check-valid",
+            &[
+                ("SUMMARY.toml", "[[map]]\ntitle = \"A\"\nurl = \"a\"\n"),
+                (
+                    "src/a.md",
+                    "---\ntitle: A\nadditional_css: [styles/a.css]\n---\n{{#include ../b.txt}}\n",
+                ),
+                ("src/styles/a.scss", ""),
+                (
+                    "src/users/<id>.md",
+                    "---\ntitle: User\nparams:\n  - {type: u32, name: id}\n---\n",
+                ),
+                ("b.txt", "B"),
+            ],
+        );
+        assert_eq!(problems(&root), Vec::<String>::new());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn problems_are_found() {
+        let root = project(
+            "check-problems",
+            &[
+                (
+                    "SUMMARY.toml",
+                    "[[map]]\ntitle = \"A\"\nurl = \"a\"\n\n[[map]]\ntitle = \"Gone\"\nurl = \"gone\"\n",
+                ),
+                (
+                    "src/a.md",
+                    "---\ntitle: A\nadditional_css: [styles/missing.css]\n---\n",
+                ),
+                ("src/b.md", "---\ntitle: B\n---\n{{#include missing.md}}\n"),
+                (
+                    "src/c.md",
+                    "---\ntitle: C\npreprocessors:\n  disable: [nope]\n---\n",
+                ),
+                ("src/d.md", "No front matter"),
+                (
+                    "src/users/<id>.md",
+                    "---\ntitle: User\nparams:\n  - {type: u32, name: other}\n---\n",
+                ),
+            ],
+        );
+        let problems = problems(&root);
+        let expected = [
+            "src/a.md: `additional_css` entry `styles/missing.css` doesn't point to any file",
+            "src/b.md: Couldn't expand `{{#include}}` in `src/b.md`",
+            "src/c.md: Unknown preprocessor `nope` in `src/c.md`",
+            "src/d.md: Invalid front matter in `src/d.md`",
+            "src/users/<id>.md: Invalid front matter in `src/users/<id>.md`: unknown parameter `other` (not in the path)",
+            "SUMMARY.toml: `gone` (\"Gone\") doesn't match any file in `src`",
+        ];
+        assert_eq!(problems.len(), expected.len(), "{problems:#?}");
+        for (problem, expected) in problems.iter().zip(expected) {
+            assert!(problem.starts_with(expected), "{problem}");
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn collisions_and_configuration() {
+        let root = project(
+            "check-collisions",
+            &[
+                ("cuteconfig.toml", "[misc\n"),
+                ("SUMMARY.toml", "map = []\n"),
+                ("src/page.md", "---\ntitle: Lower\n---\n"),
+                ("src/Page.md", "---\ntitle: Upper\n---\n"),
+            ],
+        );
+        let problems = problems(&root);
+        assert!(
+            problems[0].starts_with("cuteconfig.toml: Couldn't parse configuration `"),
+            "{problems:#?}"
+        );
+        assert!(
+            problems.contains(
+                &"src: `src/Page.md` and `src/page.md` have the same output path".to_string()
+            ),
+            "{problems:#?}"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
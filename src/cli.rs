@@ -1,7 +1,4 @@
 use clap::Parser as Parse;
-use serde::{Deserialize, Serialize};
-
-use crate::config::*;
 
 #[derive(Parse)]
 pub struct Args {
@@ -24,7 +21,44 @@ pub enum SCommand {
         #[cfg(feature = "sass")]
         #[arg(long, default_value = "sass")]
         sassbin: String,
+        /// Ignore the build cache and render every page again
+        #[arg(long)]
+        no_cache: bool,
+        /// Number of threads used to render the pages (0: one per CPU)
+        #[arg(long, short, default_value = "0")]
+        jobs: usize,
+        /// Fail if any relative link (or `#anchor`) doesn't point to a generated page or asset
+        #[arg(long)]
+        deny_broken_links: bool,
+    },
+    /// Builds your project and rebuilds only the affected parts every time a file changes
+    Watch {
+        /// Connection port
+        #[arg(long, default_value = "8080")]
+        port: u16,
+        /// Output directory
+        #[arg(long, default_value = "www")]
+        outdir: String,
+        /// Command for the sass compiler. E.g. "sass"
+        #[cfg(feature = "sass")]
+        #[arg(long, default_value = "sass")]
+        sassbin: String,
     },
+    /// Builds your project and serves it, rebuilding and reloading the open pages on every change
+    Serve {
+        /// Connection port
+        #[arg(long, default_value = "8080")]
+        port: u16,
+        /// Output directory
+        #[arg(long, default_value = "www")]
+        outdir: String,
+        /// Command for the sass compiler. E.g. "sass"
+        #[cfg(feature = "sass")]
+        #[arg(long, default_value = "sass")]
+        sassbin: String,
+    },
+    /// Validates the configuration, the summary and every page without building; fails if any problem is found
+    Check,
     /// Initializes the necessary files (configuration, placeholders...), ready to be modified.
     Init,
     /// Updates the internal configuration files in the configuration path; this is an enhanced `git pull`.
@@ -36,15 +70,3 @@ pub enum SCommand {
     /// Deletes all configuration files. `cargo uninstall` will not remove these, so before using `cargo uninstall`, use this command.
     Uninstall,
 }
-
-#[derive(Serialize)]
-pub struct Page {
-    pub config: PageConfig,
-    pub path: String,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct Map {
-    pub title: String,
-    pub url: String,
-}
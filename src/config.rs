@@ -1,27 +1,95 @@
+use std::collections::BTreeMap;
+
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 use toml::Value;
 
-use cuteness::Method;
-
-use crate::cli::Map;
+use crate::Method;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub config: FxHashMap<String, Value>,
     pub misc: MiscConfig,
+    pub preprocessors: Option<PreprocessorsConfig>,
+    /// `[[plugin]]`, see `plugins`
+    #[serde(default, rename = "plugin")]
+    pub plugins: Vec<PluginConfig>,
+    /// `[admonitions.<kind>]`, new admonition kinds or overrides of the built-in ones
+    #[serde(default)]
+    pub admonitions: BTreeMap<String, AdmonitionConfig>,
+    /// `[diagrams.<language>]`, tools rendering the code blocks of a language to SVG (see `diagrams`)
+    #[serde(default)]
+    pub diagrams: BTreeMap<String, DiagramConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdmonitionConfig {
+    /// Any CSS colour, used for the border, the title's background and the icon
+    pub color: Option<String>,
+    /// SVG icon, inline (`<svg ...>`) or the path of an `.svg` file relative to the project's root
+    pub icon: Option<String>,
+    /// Title of the admonitions that don't have one
+    pub title: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PluginConfig {
+    /// Executable or WebAssembly module (`.wasm`), paths are relative to the project's root
+    pub command: String,
+    /// Arguments for executables (ignored by WebAssembly modules)
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Seconds an executable has to answer each message before it's stopped and the build fails (default: 30). WebAssembly modules run out of fuel instead.
+    pub timeout: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiagramConfig {
+    /// Executable getting the diagram on its stdin and printing an SVG, paths are relative to the project's root
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// `[preprocessors]`, see `preprocessors::select`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PreprocessorsConfig {
+    /// Preprocessors to run first, in this order
+    #[serde(default)]
+    pub order: Vec<String>,
+    #[serde(default)]
+    pub disable: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MiscConfig {
     /// me when puro
     pub latex: Option<bool>,
+    /// `[misc.latex_macros]`: macros available in every equation, by name (e.g. `RR = '\mathbb{R}'`)
+    pub latex_macros: Option<BTreeMap<String, String>>,
     pub html_lang: Option<String>,
     pub additional_html_header: Option<String>,
     pub syntax_highlighting: Option<bool>,
+    /// Built-in syntect theme or `.tmTheme` file for `syntax_highlighting` (see `highlight::stylesheet`)
+    pub highlight_theme: Option<String>,
+    /// Directory with templates and styles overriding the global ones (see `Templates`)
+    pub theme: Option<String>,
+    /// Quotation marks, they depend on `html_lang` by default
+    pub quotes: Option<QuotesConfig>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Overrides for the quotation marks (see `typography::Quotes`)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuotesConfig {
+    /// Use this language's quotation marks
+    pub lang: Option<String>,
+    /// `["opening", "closing"]`, used for `"`
+    pub primary: Option<[String; 2]>,
+    /// `["opening", "closing"]`, used for `'` (nested quotes)
+    pub secondary: Option<[String; 2]>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PageConfig {
     pub title: String,
     pub pageconf: Option<FxHashMap<String, Value>>,
@@ -29,6 +97,18 @@ pub struct PageConfig {
     #[serde(default)]
     pub method: Method,
     pub params: Option<Vec<Param>>,
+    pub quotes: Option<QuotesConfig>,
+    pub preprocessors: Option<PagePreprocessors>,
+}
+
+/// Per-page overrides of `[preprocessors]`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PagePreprocessors {
+    /// Preprocessors disabled in `cuteconfig.toml` to run anyway
+    #[serde(default)]
+    pub enable: Vec<String>,
+    #[serde(default)]
+    pub disable: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -41,3 +121,9 @@ pub struct Param {
 pub struct SummaryConfig {
     pub map: Vec<Map>,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct Map {
+    pub title: String,
+    pub url: String,
+}
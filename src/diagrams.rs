@@ -0,0 +1,298 @@
+//! Diagrams rendered at build time (`[diagrams.<language>]` in `cuteconfig.toml`): fenced code blocks in one of those languages (e.g. ` ```dot `) are piped to a local command (e.g. `dot -Tsvg`), and the SVG it prints replaces the block.
+//!
+//! Running the tools is slow, so their SVGs are kept in the output directory (`.cuteness-diagrams`), by hash of the block and the command: a diagram is only rendered again when it or its command changes.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use handlebars::html_escape;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+
+use crate::config::DiagramConfig;
+
+/// Directory of the rendered diagrams, in the output directory
+const CACHE_DIR: &str = ".cuteness-diagrams";
+
+/// Renders diagram code blocks, see [`Diagrams::render`].
+pub struct Diagrams {
+    root: PathBuf,
+    /// `[diagrams.<language>]`, by language
+    tools: BTreeMap<String, DiagramConfig>,
+    /// `None` if nothing is written to disk (see [`SiteBuilder::in_memory`](crate::SiteBuilder::in_memory))
+    cache_dir: Option<PathBuf>,
+}
+
+impl Diagrams {
+    /// Tools are started in `root`; rendered diagrams are stored in `outdir`, if there's one.
+    pub fn new(root: &Path, tools: BTreeMap<String, DiagramConfig>, outdir: Option<&Path>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            tools,
+            cache_dir: outdir.map(|outdir| outdir.join(CACHE_DIR)),
+        }
+    }
+
+    /// Replaces the fenced code blocks whose language (the first word of their info string) has a tool by their SVG, in a `<div class="diagram diagram-<language>">`. Blocks the tool fails on are left as they are, and reported with `warn`. Diagrams rendered by previous builds are reused unless `use_cache` is `false`.
+    pub fn render<'a>(
+        &self,
+        events: Vec<Event<'a>>,
+        use_cache: bool,
+        warn: &mut impl FnMut(String),
+    ) -> Vec<Event<'a>> {
+        let mut result = Vec::with_capacity(events.len());
+        let mut events = events.into_iter();
+
+        while let Some(event) = events.next() {
+            let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = &event else {
+                result.push(event);
+                continue;
+            };
+            let lang = info
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .next()
+                .unwrap_or_default();
+            let Some(tool) = self.tools.get(lang) else {
+                result.push(event);
+                continue;
+            };
+
+            let mut block = vec![event.clone()];
+            block.extend(
+                events
+                    .by_ref()
+                    .take_while(|event| !matches!(event, Event::End(Tag::CodeBlock(_))))
+                    .collect::<Vec<_>>(),
+            );
+            let source: String = block
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Text(text) => Some(text.as_ref()),
+                    _ => None,
+                })
+                .collect();
+
+            match self.diagram(tool, &source, use_cache) {
+                Ok(svg) => result.push(Event::Html(
+                    format!(
+                        "<div class=\"diagram diagram-{}\">{svg}</div>\n",
+                        html_escape(lang)
+                    )
+                    .into(),
+                )),
+                Err(message) => {
+                    warn(format!("couldn't render `{lang}` diagram: {message}"));
+                    result.extend(block);
+                    result.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(
+                        info.clone(),
+                    ))));
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Gets the SVG of a diagram from the cache, or runs its tool.
+    fn diagram(
+        &self,
+        tool: &DiagramConfig,
+        source: &str,
+        use_cache: bool,
+    ) -> Result<String, String> {
+        let mut hasher = blake3::Hasher::new();
+        for part in std::iter::once(&tool.command).chain(&tool.args) {
+            // Separated, so `["a", "b"]` and `["ab"]` don't have the same hash.
+            hasher.update(part.as_bytes()).update(b"\0");
+        }
+        hasher.update(source.as_bytes());
+        let cached = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.svg", hasher.finalize().to_hex())));
+
+        if let Some(svg) = cached
+            .as_ref()
+            .filter(|_| use_cache)
+            .and_then(|path| fs::read_to_string(path).ok())
+        {
+            return Ok(svg);
+        }
+
+        let svg = self.run(tool, source)?;
+        if let Some(path) = &cached {
+            // Not being able to cache it only makes the next build slower.
+            let _ = fs::create_dir_all(path.parent().unwrap_or(Path::new("")))
+                .and_then(|()| fs::write(path, &svg));
+        }
+        Ok(svg)
+    }
+
+    /// Pipes `source` to the tool, returns the SVG it printed (without the XML declaration and doctype).
+    fn run(&self, tool: &DiagramConfig, source: &str) -> Result<String, String> {
+        // Paths (not bare command names) are relative to the project.
+        let program = if tool.command.contains(['/', '\\']) {
+            self.root.join(&tool.command)
+        } else {
+            PathBuf::from(&tool.command)
+        };
+
+        let mut child = Command::new(program)
+            .args(&tool.args)
+            .current_dir(&self.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("couldn't start `{}`: {e}", tool.command))?;
+
+        // Written from another thread, the tool may print before reading everything.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let source = source.to_string();
+        let writer = thread::spawn(move || stdin.write_all(source.as_bytes()));
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("`{}` failed: {e}", tool.command))?;
+        // A tool exiting without reading its input isn't a problem by itself, its exit status says if it is.
+        let _ = writer.join();
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!(
+                "`{}` failed ({}): {}",
+                tool.command,
+                output.status,
+                stderr.lines().next().unwrap_or_default()
+            ));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let start = stdout
+            .find("<svg")
+            .ok_or_else(|| format!("`{}` didn't print an SVG", tool.command))?;
+        Ok(stdout[start..].trim_end().to_string())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use pulldown_cmark::Parser;
+
+    use super::*;
+    use crate::site::markdown_options;
+
+    const SVG: &str = "<?xml version=\"1.0\"?>\n<!DOCTYPE svg>\n<svg><text>Hi</text></svg>\n";
+
+    fn tool(command: &str, args: &[&str]) -> BTreeMap<String, DiagramConfig> {
+        BTreeMap::from([(
+            "svg".to_string(),
+            DiagramConfig {
+                command: command.to_string(),
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+            },
+        )])
+    }
+
+    /// Renders `markdown`, returns its events and the warnings.
+    fn render<'a>(
+        diagrams: &Diagrams,
+        markdown: &'a str,
+        use_cache: bool,
+    ) -> (Vec<Event<'a>>, Vec<String>) {
+        let mut warnings = Vec::new();
+        let events = diagrams.render(
+            Parser::new_ext(markdown, markdown_options()).collect(),
+            use_cache,
+            &mut |m| warnings.push(m),
+        );
+        (events, warnings)
+    }
+
+    #[test]
+    fn svgs_replace_the_blocks() {
+        let diagrams = Diagrams::new(&std::env::temp_dir(), tool("cat", &[]), None);
+        let markdown = format!("Before\n\n```svg\n{SVG}```\n\n```dot\nx\n```\n");
+        let (events, warnings) = render(&diagrams, &markdown, true);
+
+        assert!(warnings.is_empty(), "{warnings:?}");
+        // Without the XML declaration and doctype
+        assert!(events.contains(&Event::Html(
+            "<div class=\"diagram diagram-svg\"><svg><text>Hi</text></svg></div>\n".into()
+        )));
+        // Other languages are left alone
+        assert!(
+            events.contains(&Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(
+                "dot".into()
+            ))))
+        );
+    }
+
+    #[test]
+    fn failures_are_warnings() {
+        let markdown = "```svg\nnot an SVG\n```\n";
+        let events: Vec<_> = Parser::new_ext(markdown, markdown_options()).collect();
+
+        for (tools, message) in [
+            (
+                tool("cuteness-no-such-tool", &[]),
+                "couldn't render `svg` diagram: couldn't start `cuteness-no-such-tool`",
+            ),
+            (
+                tool("sh", &["-c", "echo oops >&2; exit 3"]),
+                "couldn't render `svg` diagram: `sh` failed (exit status: 3): oops",
+            ),
+            (
+                tool("cat", &[]),
+                "couldn't render `svg` diagram: `cat` didn't print an SVG",
+            ),
+        ] {
+            let diagrams = Diagrams::new(&std::env::temp_dir(), tools, None);
+            let (rendered, warnings) = render(&diagrams, markdown, true);
+
+            // The block is shown as it is
+            assert_eq!(rendered, events);
+            assert_eq!(warnings.len(), 1);
+            assert!(warnings[0].starts_with(message), "{}", warnings[0]);
+        }
+    }
+
+    #[test]
+    fn diagrams_are_cached() {
+        let outdir = std::env::temp_dir().join(format!("cuteness-diagrams-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&outdir);
+        let cached = || -> Vec<PathBuf> {
+            let mut files: Vec<_> = fs::read_dir(outdir.join(CACHE_DIR))
+                .map(|dir| dir.map(|entry| entry.unwrap().path()).collect())
+                .unwrap_or_default();
+            files.sort();
+            files
+        };
+        let markdown = format!("```svg\n{SVG}```\n");
+
+        let diagrams = Diagrams::new(&std::env::temp_dir(), tool("cat", &[]), Some(&outdir));
+        render(&diagrams, &markdown, true);
+        let files = cached();
+        assert_eq!(files.len(), 1);
+
+        // Used instead of running the tool, unless the cache is disabled
+        fs::write(&files[0], "<svg>cached</svg>").unwrap();
+        let svg = |use_cache| match &render(&diagrams, &markdown, use_cache).0[..] {
+            [Event::Html(html)] => html.to_string(),
+            events => panic!("{events:?}"),
+        };
+        assert!(svg(true).contains("<svg>cached</svg>"));
+        assert!(svg(false).contains("<svg><text>Hi</text></svg>"));
+
+        // The key is the source and the command
+        render(&diagrams, &markdown.replace("Hi", "Bye"), true);
+        let diagrams = Diagrams::new(&std::env::temp_dir(), tool("cat", &["-"]), Some(&outdir));
+        render(&diagrams, &markdown, true);
+        assert_eq!(cached().len(), 3);
+
+        fs::remove_dir_all(&outdir).unwrap();
+    }
+}
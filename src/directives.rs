@@ -0,0 +1,313 @@
+//! Container directives: `::: <name> key=value` ... `:::` blocks rendered by the project's `templates/directives/<name>.hbs` templates.
+//!
+//! Directives are found in the Markdown before parsing it ([`mark`]): their lines are replaced by HTML comments, so what's between them is parsed (and preprocessed) like the rest of the page. Once the page is rendered to HTML, every directive is replaced by its template, innermost first ([`render`]).
+
+use std::collections::BTreeMap;
+
+use handlebars::Handlebars;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{Error, Result};
+
+const START: &str = "<!--cuteness:directive:";
+const END: &str = "<!--cuteness:/directive:";
+
+/// A directive's opening line (without the colons): `<name> key=value key="quoted value" flag`
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Directive {
+    /// Letters, digits, `-` and `_`
+    pub name: String,
+    /// Flags (attributes without a value) are `"true"`.
+    pub attrs: BTreeMap<String, String>,
+}
+
+impl Directive {
+    /// Parses what follows the colons of a directive's opening line, `None` if the name isn't valid.
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let (name, mut rest) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return None;
+        }
+
+        let mut attrs = BTreeMap::new();
+        rest = rest.trim_start();
+        while !rest.is_empty() {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(rest.len());
+            let key = &rest[..end];
+            rest = &rest[end..];
+
+            let value = match rest.strip_prefix('=') {
+                Some(value) => match value.strip_prefix('"') {
+                    Some(quoted) => {
+                        let end = quoted.find('"').unwrap_or(quoted.len());
+                        rest = quoted.get(end + 1..).unwrap_or_default();
+                        &quoted[..end]
+                    }
+                    None => {
+                        let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                        rest = &value[end..];
+                        &value[..end]
+                    }
+                },
+                None => "true",
+            };
+            if !key.is_empty() {
+                attrs.insert(key.to_string(), value.to_string());
+            }
+            rest = rest.trim_start();
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            attrs,
+        })
+    }
+}
+
+/// Replaces the lines opening (`::: name`) and closing (`:::`) directives by HTML comments, returns the new Markdown and the directives, by number.
+///
+/// Lines in code blocks are left alone, except in admonitions (their body is Markdown too). Directives that aren't closed are closed at the end of the page, and reported with `warn`.
+pub fn mark(markdown: &str, warn: &mut impl FnMut(String)) -> (String, Vec<Directive>) {
+    let mut result = String::with_capacity(markdown.len());
+    let mut directives: Vec<Directive> = Vec::new();
+    // Numbers of the open directives
+    let mut open = Vec::new();
+    // Open code fences: their character, their length and whether they're admonitions
+    let mut fences: Vec<(char, usize, bool)> = Vec::new();
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+
+        // * Code blocks ===========================
+
+        let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let fence_len =
+            fence_char.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+
+        if let Some(&(c, len, _)) = fences.last() {
+            if fence_char == Some(c) && fence_len >= len && trimmed[fence_len..].trim().is_empty() {
+                fences.pop();
+                result.push_str(line);
+                continue;
+            }
+        }
+        if fences.last().is_some_and(|&(_, _, admonition)| !admonition) {
+            result.push_str(line);
+            continue;
+        }
+        if let Some(c) = fence_char.filter(|_| fence_len >= 3) {
+            let info = trimmed[fence_len..].trim_start();
+            fences.push((c, fence_len, info.starts_with("admonish")));
+            result.push_str(line);
+            continue;
+        }
+
+        // * Directives ============================
+
+        let colons = trimmed.len() - trimmed.trim_start_matches(':').len();
+        if colons >= 3 {
+            let rest = &trimmed[colons..];
+            if rest.trim().is_empty() {
+                if let Some(number) = open.pop() {
+                    result.push_str(&format!("{indent}{END}{number}-->\n"));
+                    continue;
+                }
+            } else if let Some(directive) = Directive::parse(rest) {
+                open.push(directives.len());
+                result.push_str(&format!("{indent}{START}{}-->\n", directives.len()));
+                directives.push(directive);
+                continue;
+            }
+        }
+
+        result.push_str(line);
+    }
+
+    if !result.is_empty() && !result.ends_with('\n') {
+        result.push('\n');
+    }
+    while let Some(number) = open.pop() {
+        warn(format!(
+            "directive `{}` isn't closed (with `:::`)",
+            directives[number].name
+        ));
+        result.push_str(&format!("{END}{number}-->\n"));
+    }
+
+    (result, directives)
+}
+
+/// Replaces every directive marked by [`mark`] in a rendered page by its template (`directives/<name>`), innermost first.
+///
+/// Templates get the directive's `name`, `attrs`, `content` (the rendered HTML between its lines), `id` (its number in the page, e.g. to link tabs to their panels) and `page` (the page's front matter and table of contents). Directives without a template are reported with `warn`, only their content is kept.
+pub fn render(
+    html: &str,
+    directives: &[Directive],
+    reg: &Handlebars,
+    page: &serde_json::Value,
+    warn: &mut impl FnMut(String),
+) -> Result<String> {
+    let mut html = html.to_string();
+
+    // The first closing comment is the one of an innermost directive.
+    while let Some(end) = html.find(END) {
+        let Some(number) = html[end + END.len()..]
+            .split_once("-->")
+            .and_then(|(number, _)| number.parse::<usize>().ok())
+            .filter(|number| *number < directives.len())
+        else {
+            break;
+        };
+        let start_marker = format!("{START}{number}-->");
+        let end_marker = format!("{END}{number}-->");
+        let Some(start) = html[..end].rfind(&start_marker) else {
+            // Its opening comment was lost (e.g. by a preprocessor), only drop this one.
+            html.replace_range(end..end + end_marker.len(), "");
+            continue;
+        };
+        let content = html[start + start_marker.len()..end].trim_matches('\n');
+
+        let directive = &directives[number];
+        let template = format!("directives/{}", directive.name);
+        let rendered = if reg.has_template(&template) {
+            reg.render(
+                &template,
+                &json!({
+                    "name": directive.name,
+                    "attrs": directive.attrs,
+                    "content": content,
+                    "id": number,
+                    "page": page,
+                }),
+            )
+            .map_err(|e| Error::template(format!("{template}.hbs"), e))?
+        } else {
+            warn(format!(
+                "unknown directive `{0}` (add a `templates/directives/{0}.hbs` template)",
+                directive.name
+            ));
+            content.to_string()
+        };
+
+        html.replace_range(start..end + end_marker.len(), &rendered);
+    }
+
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(templates: &[(&str, &str)]) -> Handlebars<'static> {
+        let mut reg = Handlebars::new();
+        reg.register_escape_fn(handlebars::no_escape);
+        for (name, template) in templates {
+            reg.register_template_string(&format!("directives/{name}"), template)
+                .unwrap();
+        }
+        reg
+    }
+
+    /// Marks and renders `html` (already rendered, directives' lines included), returns the HTML and the warnings.
+    fn render_with(html: &str, templates: &[(&str, &str)]) -> (String, Vec<String>) {
+        let mut warnings = Vec::new();
+        let (marked, directives) = mark(html, &mut |message| warnings.push(message));
+        let html = render(
+            &marked,
+            &directives,
+            &registry(templates),
+            &json!({"title": "Page"}),
+            &mut |message| warnings.push(message),
+        )
+        .unwrap();
+        (html, warnings)
+    }
+
+    #[test]
+    fn parse_attributes() {
+        assert_eq!(
+            Directive::parse(" card title=\"Hello world\" wide size=2 "),
+            Some(Directive {
+                name: "card".to_string(),
+                attrs: BTreeMap::from([
+                    ("size".to_string(), "2".to_string()),
+                    ("title".to_string(), "Hello world".to_string()),
+                    ("wide".to_string(), "true".to_string()),
+                ]),
+            })
+        );
+        assert_eq!(
+            Directive::parse("hero_banner-2").map(|directive| directive.attrs.len()),
+            Some(0)
+        );
+        assert_eq!(Directive::parse(""), None);
+        assert_eq!(Directive::parse("not/a directive"), None);
+    }
+
+    #[test]
+    fn mark_lines() {
+        let (marked, directives) = mark(
+            "::: card\nText\n\n```\n::: tabs\n:::\n```\n\n```admonish\n:::: note\n::::\n```\n:::",
+            &mut |message| panic!("{message}"),
+        );
+        assert_eq!(
+            marked,
+            "<!--cuteness:directive:0-->\nText\n\n```\n::: tabs\n:::\n```\n\n```admonish\n<!--cuteness:directive:1-->\n<!--cuteness:/directive:1-->\n```\n<!--cuteness:/directive:0-->\n"
+        );
+        let names: Vec<_> = directives.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["card", "note"]);
+    }
+
+    #[test]
+    fn unclosed_directives_warn() {
+        let mut warnings = Vec::new();
+        let (marked, _) = mark("::: card\nText", &mut |message| warnings.push(message));
+        assert_eq!(
+            marked,
+            "<!--cuteness:directive:0-->\nText\n<!--cuteness:/directive:0-->\n"
+        );
+        assert_eq!(warnings, ["directive `card` isn't closed (with `:::`)"]);
+    }
+
+    #[test]
+    fn render_templates() {
+        let (html, warnings) = render_with(
+            "::: columns count=2\n::: column\nA\n:::\n::: column\nB\n:::\n:::\n",
+            &[
+                (
+                    "columns",
+                    "<div class=\"columns-{{attrs.count}}\">{{content}}</div>",
+                ),
+                (
+                    "column",
+                    "<div id=\"{{name}}-{{id}}\" title=\"{{page.title}}\">{{content}}</div>",
+                ),
+            ],
+        );
+        assert_eq!(
+            html,
+            "<div class=\"columns-2\"><div id=\"column-1\" title=\"Page\">A</div>\n<div id=\"column-2\" title=\"Page\">B</div></div>\n"
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn unknown_directives_keep_their_content() {
+        let (html, warnings) = render_with("::: tabs\nContent\n:::\n", &[]);
+        assert_eq!(html, "Content\n");
+        assert_eq!(
+            warnings,
+            ["unknown directive `tabs` (add a `templates/directives/tabs.hbs` template)"]
+        );
+    }
+}
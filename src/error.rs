@@ -0,0 +1,135 @@
+//! Errors returned by the library, so tools built on top of cuteness can handle them instead of crashing.
+
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use thiserror::Error;
+
+/// Anything that can go wrong while setting up, initializing or building a project.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A file the project needs doesn't exist (e.g. building outside of a project)
+    #[error("Couldn't find `{}`", .0.display())]
+    NotFound(PathBuf),
+    /// `init` was run in a directory that already contains a project
+    #[error("`{}` already exists", .0.display())]
+    AlreadyExists(PathBuf),
+    /// `cuteconfig.toml` isn't valid
+    #[error("Couldn't parse configuration `{}`", path.display())]
+    Config {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    /// `SUMMARY.toml` isn't valid
+    #[error("Couldn't parse summary `{}`", path.display())]
+    Summary {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    /// A page's front matter isn't valid
+    #[error("Invalid front matter in `{}`: {message}", path.display())]
+    FrontMatter { path: PathBuf, message: String },
+    /// A `{{#include}}` points to a file (or an anchor) that doesn't exist, or to a file including it
+    #[error("Couldn't expand `{{{{#include}}}}` in `{}`: {message}", path.display())]
+    Include { path: PathBuf, message: String },
+    /// `[preprocessors]` or a page's front matter refers to a preprocessor that doesn't exist
+    #[error("Unknown preprocessor `{name}` in `{}`", path.display())]
+    UnknownPreprocessor { name: String, path: PathBuf },
+    /// Two pages would be written to the same file or routed by the same handler
+    #[error("`{}` and `{}` have the same {what}", first.display(), second.display())]
+    Collision {
+        first: PathBuf,
+        second: PathBuf,
+        what: &'static str,
+    },
+    /// A template couldn't be found, registered or rendered
+    #[error("Template error in `{name}`")]
+    Template {
+        name: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// An external command (`git`, `sass`...) couldn't be started or failed
+    #[error("`{command}` failed: {reason}")]
+    Tool { command: String, reason: String },
+    /// A plugin (`[[plugin]]`) misbehaved or couldn't transform a page
+    #[error("Plugin `{command}` failed: {message}")]
+    Plugin { command: String, message: String },
+    /// `highlight_theme` isn't a built-in theme or a valid `.tmTheme` file
+    #[error("Couldn't load highlight theme `{name}`: {message}")]
+    HighlightTheme { name: String, message: String },
+    /// Reading or writing a file failed
+    #[error("{message}")]
+    Io {
+        message: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The build cache couldn't be serialized
+    #[error("Couldn't serialize build cache")]
+    Cache(#[source] serde_json::Error),
+    /// The thread pool used to render pages couldn't be created
+    #[error("Couldn't create thread pool")]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    pub(crate) fn template(
+        name: impl Into<String>,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        Self::Template {
+            name: name.into(),
+            source: Box::new(source),
+        }
+    }
+}
+
+/// Adds a message to I/O errors, like `anyhow::Context`.
+pub(crate) trait Context<T> {
+    fn context(self, message: impl Into<String>) -> Result<T>;
+    fn with_context(self, message: impl FnOnce() -> String) -> Result<T>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn context(self, message: impl Into<String>) -> Result<T> {
+        self.map_err(|source| Error::Io {
+            message: message.into(),
+            source,
+        })
+    }
+
+    fn with_context(self, message: impl FnOnce() -> String) -> Result<T> {
+        self.map_err(|source| Error::Io {
+            message: message(),
+            source,
+        })
+    }
+}
+
+/// Runs an external command, failing if it can't be started or doesn't exit successfully.
+pub(crate) fn run(command: &mut Command) -> Result<()> {
+    let name = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let status = command.status().map_err(|e| Error::Tool {
+        command: name.clone(),
+        reason: e.to_string(),
+    })?;
+
+    if !status.success() {
+        return Err(Error::Tool {
+            command: name,
+            reason: status.to_string(),
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+//! Syntax highlighting of code blocks at build time, with [`syntect`].
+//!
+//! Code is split in `<span>`s with classes (prefixed by `hl-`, so they don't collide with the site's), their colors come from the theme's stylesheet, written to `styles/highlight.css`. Its braces are written `&#123;`, so the page's Handlebars pass doesn't take `{{` in highlighted code for a template.
+
+use std::path::Path;
+
+use handlebars::html_escape;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::{Error, Result};
+
+/// Theme used if `highlight_theme` isn't set
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Highlights code blocks, see [`Highlighter::highlight`].
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter {
+    /// Loads the built-in syntaxes.
+    pub fn new() -> Self {
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+        }
+    }
+
+    /// Highlights the fenced code blocks of a page, using the first word of their info string as language (e.g. ` ```rust ` or ` ```rs,ignore `). Blocks without a language, or with one that isn't known, are left as they are.
+    pub fn highlight<'a>(&self, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+        let mut result = Vec::with_capacity(events.len());
+        let mut events = events.into_iter();
+
+        while let Some(event) = events.next() {
+            let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = &event else {
+                result.push(event);
+                continue;
+            };
+            let lang = info
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .next()
+                .unwrap_or_default();
+            let Some(syntax) = self.syntaxes.find_syntax_by_token(lang) else {
+                result.push(event);
+                continue;
+            };
+
+            let code: String = events
+                .by_ref()
+                .take_while(|event| !matches!(event, Event::End(Tag::CodeBlock(_))))
+                .filter_map(|event| match event {
+                    Event::Text(text) => Some(text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .concat();
+
+            let mut generator =
+                ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+            let highlighted = LinesWithEndings::from(&code)
+                .try_for_each(|line| generator.parse_html_for_line_which_includes_newline(line))
+                .map(|_| generator.finalize())
+                // The syntax couldn't parse it, it's still better shown without colors than not at all.
+                .unwrap_or_else(|_| html_escape(&code))
+                .replace('{', "&#123;");
+
+            result.push(Event::Html(
+                format!(
+                    "<pre class=\"hl-code\"><code class=\"language-{}\">{highlighted}</code></pre>\n",
+                    html_escape(lang)
+                )
+                .into(),
+            ));
+        }
+
+        result
+    }
+}
+
+/// Generates the stylesheet of a theme: the name of a built-in one (e.g. `InspiredGitHub`, `base16-ocean.dark`, `Solarized (light)`) or the path to a `.tmTheme` file, relative to `root`.
+pub fn stylesheet(root: &Path, theme: &str) -> Result<String> {
+    let error = |message: String| Error::HighlightTheme {
+        name: theme.to_string(),
+        message,
+    };
+
+    let theme = if theme.ends_with(".tmTheme") {
+        ThemeSet::get_theme(root.join(theme)).map_err(|e| error(e.to_string()))?
+    } else {
+        let mut themes = ThemeSet::load_defaults().themes;
+        let names = themes.keys().cloned().collect::<Vec<_>>().join("`, `");
+        themes
+            .remove(theme)
+            .ok_or_else(|| error(format!("the built-in themes are `{names}`")))?
+    };
+
+    css_for_theme_with_class_style(&theme, CLASS_STYLE).map_err(|e| error(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use handlebars::Handlebars;
+    use pulldown_cmark::{html, Parser};
+
+    use super::*;
+    use crate::site::markdown_options;
+
+    fn highlight(markdown: &str) -> Vec<Event<'_>> {
+        Highlighter::new().highlight(Parser::new_ext(markdown, markdown_options()).collect())
+    }
+
+    #[test]
+    fn known_languages() {
+        let events = highlight("```rs,ignore\nfn main() {}\n```");
+        let [Event::Html(html)] = events.as_slice() else {
+            panic!("{events:?}");
+        };
+        assert!(
+            html.starts_with(r#"<pre class="hl-code"><code class="language-rs">"#),
+            "{html}"
+        );
+        assert!(
+            html.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#),
+            "{html}"
+        );
+        assert!(html.ends_with("</code></pre>\n"), "{html}");
+    }
+
+    #[test]
+    fn unknown_languages_are_left_alone() {
+        for markdown in ["```not-a-language\n<b>x</b>\n```", "```\nx\n```", "    x\n"] {
+            let events: Vec<_> = Parser::new_ext(markdown, markdown_options()).collect();
+            assert_eq!(highlight(markdown), events);
+        }
+    }
+
+    #[test]
+    fn braces_arent_templates() {
+        let mut html = String::new();
+        html::push_html(
+            &mut html,
+            highlight("```rust\nprintln!(\"{{}} {}\", x);\n```").into_iter(),
+        );
+        assert!(!html.contains('{'), "{html}");
+        assert!(html.contains("&#123;&#123;"), "{html}");
+        // Left as it is by the page's Handlebars pass
+        assert_eq!(Handlebars::new().render_template(&html, &()).unwrap(), html);
+    }
+}
@@ -0,0 +1,583 @@
+//! `{{#include path}}`: pulls another file, or part of it, into a page before it's parsed (e.g. code examples kept as compilable files, with a code block's `include=path` option).
+//!
+//! Paths are relative to the file with the `{{#include}}`. Included files can include other files too, but not themselves (directly or not). Files outside the project (e.g. `/etc/passwd`, or through a symbolic link) are never included, so building a branch you don't trust can't publish files from the machine building it.
+
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{Error, Result};
+
+lazy_static! {
+    static ref REINCLUDE: Regex = Regex::new(r"\{\{#include\s+([^}]+?)\s*\}\}").unwrap();
+}
+
+/// Replaces `{{` in included text (and in `{{#include}}`s that are shown as is), so the Handlebars pass over the page leaves it alone. See [`restore`].
+const BRACES: char = '\u{E003}';
+
+/// Expands the `{{#include}}`s of `markdown`, the content of `path` (relative to `root`). Returns the new Markdown and every file it included, relative to `root`. `{{#include}}`s of files outside of `root` are removed, and reported with `warn`.
+///
+/// `{{#include}}`s in code spans and code blocks are shown as is, like the ones escaped with a backslash (`\{{#include}}`). Code blocks with an `include=<path>` option (e.g. ` ```rust include=main.rs:setup `) get the file as their content instead.
+///
+/// Included text isn't rendered by Handlebars: its `{{` are replaced by a placeholder, call [`restore`] on the rendered page.
+pub fn expand(
+    root: &Path,
+    path: &Path,
+    markdown: &str,
+    warn: &mut impl FnMut(String),
+) -> Result<(String, BTreeSet<PathBuf>)> {
+    let mut included = BTreeSet::new();
+    let canonical_root = root.canonicalize().map_err(|e| Error::Include {
+        path: path.to_path_buf(),
+        message: format!("couldn't find the project's root: {e}"),
+    })?;
+    let mut expansion = Expansion {
+        root,
+        canonical_root,
+        stack: vec![path.to_path_buf()],
+        included: &mut included,
+        warn,
+    };
+    let markdown = expansion.expand(markdown)?;
+    Ok((markdown, included))
+}
+
+/// Puts back the `{{` of included text, once the page was rendered by Handlebars.
+pub fn restore(html: &str) -> String {
+    html.replace(BRACES, "{{")
+}
+
+/// Hides the `{{` of `text` from Handlebars.
+fn protect(text: &str) -> String {
+    text.replace("{{", &BRACES.to_string())
+}
+
+struct Expansion<'a, W> {
+    root: &'a Path,
+    /// Included files have to be in it
+    canonical_root: PathBuf,
+    /// The file being expanded and the ones including it, to find cycles
+    stack: Vec<PathBuf>,
+    included: &'a mut BTreeSet<PathBuf>,
+    warn: &'a mut W,
+}
+
+impl<W: FnMut(String)> Expansion<'_, W> {
+    /// Expands a file, code blocks are found like [`directives::mark`](crate::directives::mark) does.
+    fn expand(&mut self, content: &str) -> Result<String> {
+        let mut result = String::with_capacity(content.len());
+        // Text since the last code block, expanded at once as code spans can be on several lines.
+        let mut text = String::new();
+        // Open code fences: their character, their length, whether they're admonitions (their body is Markdown) and whether their content is included
+        let mut fences: Vec<(char, usize, bool, bool)> = Vec::new();
+
+        for line in content.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            let indent = &line[..line.len() - trimmed.len()];
+
+            let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+            let fence_len =
+                fence_char.map_or(0, |c| trimmed.len() - trimmed.trim_start_matches(c).len());
+
+            if let Some(&(c, len, _, _)) = fences.last() {
+                if fence_char == Some(c)
+                    && fence_len >= len
+                    && trimmed[fence_len..].trim().is_empty()
+                {
+                    fences.pop();
+                    result.push_str(&self.text(&std::mem::take(&mut text))?);
+                    result.push_str(line);
+                    continue;
+                }
+            }
+            match fences.last() {
+                // Replaced by the included file
+                Some(&(_, _, _, true)) => continue,
+                Some(&(_, _, false, _)) => {
+                    result.push_str(&line.replace("{{#include", &format!("{BRACES}#include")));
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(c) = fence_char.filter(|_| fence_len >= 3) {
+                result.push_str(&self.text(&std::mem::take(&mut text))?);
+                result.push_str(line);
+
+                let info = trimmed[fence_len..].trim_start();
+                let spec = info
+                    .split_whitespace()
+                    .find_map(|option| option.strip_prefix("include="));
+                fences.push((c, fence_len, info.starts_with("admonish"), spec.is_some()));
+
+                if let Some(spec) = spec {
+                    let file = self.resolve(spec);
+                    if let Some(code) = self.read(&file, spec, &format!("include={spec}"))? {
+                        for code_line in code.lines() {
+                            result.push_str(indent);
+                            result.push_str(&protect(code_line));
+                            result.push('\n');
+                        }
+                    }
+                }
+                continue;
+            }
+
+            text.push_str(line);
+        }
+
+        result.push_str(&self.text(&text)?);
+        Ok(result)
+    }
+
+    /// Expands the `{{#include}}`s of text outside of code blocks.
+    fn text(&mut self, text: &str) -> Result<String> {
+        let spans = code_spans(text);
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+
+        for cap in REINCLUDE.captures_iter(text) {
+            let whole = cap.get(0).unwrap();
+            let in_code = spans.iter().any(|span| span.contains(&whole.start()));
+            let escaped = !in_code && text[..whole.start()].ends_with('\\');
+            if in_code || escaped {
+                let start = whole.start() - usize::from(escaped);
+                result.push_str(&text[last..start]);
+                result.push(BRACES);
+                last = whole.start() + 2;
+                continue;
+            }
+            result.push_str(&text[last..whole.start()]);
+            last = whole.end();
+
+            let spec = cap[1].trim();
+            let file = self.resolve(spec);
+            if let Some(start) = self.stack.iter().position(|path| *path == file) {
+                let cycle: Vec<String> = self.stack[start..]
+                    .iter()
+                    .chain([&file])
+                    .map(|path| format!("`{}`", path.display()))
+                    .collect();
+                return Err(self.error(format!("include cycle {}", cycle.join(" → "))));
+            }
+
+            let Some(included) = self.read(&file, spec, whole.as_str())? else {
+                continue;
+            };
+            self.stack.push(file);
+            let expanded = self.expand(&included)?;
+            self.stack.pop();
+
+            // The line with the `{{#include}}` keeps its own line break.
+            result.push_str(&protect(expanded.strip_suffix('\n').unwrap_or(&expanded)));
+        }
+
+        result.push_str(&text[last..]);
+        Ok(result)
+    }
+
+    /// Path of the file an include points to (`spec` is `path` or `path:selector`), relative to the root.
+    fn resolve(&self, spec: &str) -> PathBuf {
+        let current = self
+            .stack
+            .last()
+            .map(PathBuf::as_path)
+            .unwrap_or(Path::new(""));
+        let file = spec.split_once(':').map_or(spec, |(file, _)| file);
+        normalize(&current.parent().unwrap_or(Path::new("")).join(file.trim()))
+    }
+
+    /// Reads the lines of `file` that `spec`'s selector asks for, `None` if it's outside of the project (reported with `warn`, `include` is the include as written).
+    fn read(&mut self, file: &Path, spec: &str, include: &str) -> Result<Option<String>> {
+        let selector = spec
+            .split_once(':')
+            .map_or("", |(_, selector)| selector.trim());
+
+        let canonical = self
+            .root
+            .join(file)
+            .canonicalize()
+            .map_err(|e| self.error(format!("couldn't read `{}`: {e}", file.display())))?;
+        if !canonical.starts_with(&self.canonical_root) {
+            let current = self.stack.last().cloned().unwrap_or_default();
+            (self.warn)(format!(
+                "`{include}` in `{}` points outside of the project, it isn't included",
+                current.display()
+            ));
+            return Ok(None);
+        }
+
+        let text = read_to_string(&canonical)
+            .map_err(|e| self.error(format!("couldn't read `{}`: {e}", file.display())))?;
+        let text = select(&text, selector).ok_or_else(|| {
+            self.error(format!(
+                "`{}` has no anchor `{selector}` (`ANCHOR: {selector}`)",
+                file.display()
+            ))
+        })?;
+
+        self.included.insert(file.to_path_buf());
+        Ok(Some(text))
+    }
+
+    /// An error in the file being expanded
+    fn error(&self, message: String) -> Error {
+        Error::Include {
+            path: self.stack.last().cloned().unwrap_or_default(),
+            message,
+        }
+    }
+}
+
+/// Byte ranges of the code spans (`` `code` ``) of `text`: from a run of backticks to the next run of the same length, in the same paragraph.
+fn code_spans(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let run_end = |start: usize| {
+        start
+            + bytes[start..]
+                .iter()
+                .take_while(|byte| **byte == b'`')
+                .count()
+    };
+
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'`' || (i > 0 && bytes[i - 1] == b'\\') {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i = run_end(start);
+        let len = i - start;
+
+        let mut j = i;
+        while j < bytes.len() {
+            match bytes[j] {
+                b'`' => {
+                    let end = run_end(j);
+                    if end - j == len {
+                        spans.push(start..end);
+                        i = end;
+                        break;
+                    }
+                    j = end;
+                }
+                b'\n'
+                    if text[j + 1..]
+                        .trim_start_matches([' ', '\t'])
+                        .starts_with('\n') =>
+                {
+                    break;
+                }
+                _ => j += 1,
+            }
+        }
+    }
+    spans
+}
+
+/// Selects the lines of `text` that `selector` asks for:
+///
+/// * Nothing: every line.
+/// * `a`: line `a` (they start at 1).
+/// * `a:`, `:b` or `a:b`: lines `a` to `b` (both included), from the first one or to the last one if missing.
+/// * Anything else is an anchor: the lines between `ANCHOR: <name>` and `ANCHOR_END: <name>` (usually in comments), without other anchors' lines. `None` if there isn't one.
+fn select(text: &str, selector: &str) -> Option<String> {
+    if selector.is_empty() {
+        return Some(text.to_string());
+    }
+
+    let number = |s: &str| -> Option<Option<usize>> {
+        match s.trim() {
+            "" => Some(None),
+            s => s.parse().ok().map(Some),
+        }
+    };
+    let range = match selector.split_once(':') {
+        Some((start, end)) => number(start).zip(number(end)),
+        None => number(selector).map(|line| (line, line)),
+    };
+
+    let lines: Vec<&str> = text.lines().collect();
+    let selected: Vec<&str> = match range {
+        Some((start, end)) => {
+            let start = start.unwrap_or(1).max(1) - 1;
+            let end = end.unwrap_or(lines.len()).min(lines.len());
+            lines.get(start..end).unwrap_or_default().to_vec()
+        }
+        None => {
+            let start = Regex::new(&format!(r"ANCHOR:\s*{}\b", regex::escape(selector))).ok()?;
+            let end = Regex::new(&format!(r"ANCHOR_END:\s*{}\b", regex::escape(selector))).ok()?;
+
+            let first = lines.iter().position(|line| start.is_match(line))?;
+            lines[first + 1..]
+                .iter()
+                .take_while(|line| !end.is_match(line))
+                .filter(|line| !line.contains("ANCHOR:") && !line.contains("ANCHOR_END:"))
+                .copied()
+                .collect()
+        }
+    };
+
+    Some(selected.join("\n") + "\n")
+}
+
+/// Resolves `.` and `..` without touching the file system (included files don't have to be in `src`).
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const EXAMPLE: &str = "\
+// ANCHOR: all
+use std::io;
+// ANCHOR: main
+fn main() {
+    println!(\"Hi\");
+}
+// ANCHOR_END: main
+// ANCHOR_END: all
+";
+
+    /// Creates a project with `files` (path relative to the root, contents) in a new temporary directory.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("cuteness-include-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    /// Expands `src/index.md` of the project, returns the Markdown (or the error) and the warnings.
+    fn expand_index(root: &Path) -> (Result<String>, Vec<String>) {
+        let mut warnings = Vec::new();
+        let markdown = fs::read_to_string(root.join("src/index.md")).unwrap();
+        let result = expand(root, Path::new("src/index.md"), &markdown, &mut |m| {
+            warnings.push(m)
+        });
+        (result.map(|(markdown, _)| markdown), warnings)
+    }
+
+    #[test]
+    fn line_ranges() {
+        assert_eq!(select(EXAMPLE, "4").unwrap(), "fn main() {\n");
+        assert_eq!(
+            select(EXAMPLE, "4:6").unwrap(),
+            "fn main() {\n    println!(\"Hi\");\n}\n"
+        );
+        assert_eq!(select(EXAMPLE, "8:").unwrap(), "// ANCHOR_END: all\n");
+        assert_eq!(select(EXAMPLE, ":1").unwrap(), "// ANCHOR: all\n");
+        assert_eq!(select(EXAMPLE, "").unwrap(), EXAMPLE);
+        // Out of the file: nothing, not an error.
+        assert_eq!(select(EXAMPLE, "20:30").unwrap(), "\n");
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(
+            select(EXAMPLE, "main").unwrap(),
+            "fn main() {\n    println!(\"Hi\");\n}\n"
+        );
+        // Other anchors' lines are left out.
+        assert_eq!(
+            select(EXAMPLE, "all").unwrap(),
+            "use std::io;\nfn main() {\n    println!(\"Hi\");\n}\n"
+        );
+        assert_eq!(select(EXAMPLE, "mai"), None);
+        assert_eq!(select(EXAMPLE, "missing"), None);
+    }
+
+    #[test]
+    fn nested_includes() {
+        let root = project(
+            "nested",
+            &[
+                ("src/index.md", "{{#include ../examples/part.md}}\n"),
+                ("examples/part.md", "```rust include=main.rs:main\n```\n"),
+                ("examples/main.rs", EXAMPLE),
+            ],
+        );
+        let mut warnings = Vec::new();
+        let (markdown, included) = expand(
+            &root,
+            Path::new("src/index.md"),
+            &fs::read_to_string(root.join("src/index.md")).unwrap(),
+            &mut |m| warnings.push(m),
+        )
+        .unwrap();
+
+        assert_eq!(
+            markdown,
+            "```rust include=main.rs:main\nfn main() {\n    println!(\"Hi\");\n}\n```\n"
+        );
+        assert_eq!(
+            included,
+            BTreeSet::from([
+                PathBuf::from("examples/main.rs"),
+                PathBuf::from("examples/part.md")
+            ])
+        );
+        assert!(warnings.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let root = project(
+            "cycle",
+            &[
+                ("src/index.md", "{{#include a.md}}"),
+                ("src/a.md", "{{#include b.md}}"),
+                ("src/b.md", "{{#include ./a.md}}"),
+            ],
+        );
+        let error = expand_index(&root).0.unwrap_err().to_string();
+        assert!(
+            error.contains("include cycle `src/a.md` → `src/b.md` → `src/a.md`"),
+            "{error}"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn missing_files_and_anchors_are_errors() {
+        let root = project(
+            "missing",
+            &[
+                ("src/index.md", "{{#include main.rs:nope}}"),
+                ("src/main.rs", EXAMPLE),
+            ],
+        );
+        let error = expand_index(&root).0.unwrap_err().to_string();
+        assert!(error.contains("has no anchor `nope`"), "{error}");
+
+        fs::write(root.join("src/index.md"), "{{#include missing.rs}}").unwrap();
+        let error = expand_index(&root).0.unwrap_err().to_string();
+        assert!(error.contains("couldn't read `src/missing.rs`"), "{error}");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn files_outside_the_project_are_left_out() {
+        let root = project("outside", &[("src/index.md", "")]);
+        fs::write(root.with_extension("secret"), "secret").unwrap();
+        let secret = root.with_extension("secret");
+        let secret_name = secret.file_name().unwrap().to_string_lossy();
+
+        for include in [
+            format!("{{{{#include ../../{secret_name}}}}}"),
+            format!("{{{{#include {}}}}}", secret.display()),
+        ] {
+            fs::write(root.join("src/index.md"), format!("A{include}B")).unwrap();
+            let (markdown, warnings) = expand_index(&root);
+            assert_eq!(markdown.unwrap(), "AB");
+            assert_eq!(warnings.len(), 1);
+            assert!(
+                warnings[0].contains("in `src/index.md` points outside of the project"),
+                "{warnings:?}"
+            );
+        }
+
+        fs::remove_file(secret).unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(
+            normalize(Path::new("src/./a/../b.md")),
+            Path::new("src/b.md")
+        );
+        assert_eq!(normalize(Path::new("src/../../b.md")), Path::new("../b.md"));
+    }
+
+    #[test]
+    fn included_text_isnt_a_template() {
+        let root = project(
+            "braces",
+            &[
+                (
+                    "src/index.md",
+                    "{{#include main.rs}}\n```rust include=main.rs\n```\n",
+                ),
+                ("src/main.rs", "println!(\"{{}}\", 1);\n"),
+            ],
+        );
+        let markdown = expand_index(&root).0.unwrap();
+        assert!(!markdown.contains("{{"), "{markdown}");
+        assert_eq!(
+            restore(&markdown),
+            "println!(\"{{}}\", 1);\n```rust include=main.rs\nprintln!(\"{{}}\", 1);\n```\n"
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn code_and_escaped_includes_are_shown() {
+        // `missing.md` doesn't exist, none of them is included.
+        let markdown = "`{{#include missing.md}}`, ``a `{{#include missing.md}}``\n\n```markdown\n{{#include missing.md}}\n```\n\\{{#include missing.md}}\n";
+        let root = project("shown", &[("src/index.md", markdown)]);
+        let expanded = expand_index(&root).0.unwrap();
+
+        assert!(!expanded.contains("{{"), "{expanded}");
+        assert_eq!(restore(&expanded), markdown.replace("\\{{", "{{"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn code_blocks_include_files() {
+        let root = project(
+            "code-blocks",
+            &[
+                (
+                    "src/index.md",
+                    "* Main:\n\n  ```rust include=../examples/main.rs:main\n  replaced\n  ```\n",
+                ),
+                ("examples/main.rs", EXAMPLE),
+            ],
+        );
+        let (markdown, warnings) = expand_index(&root);
+        assert_eq!(
+            markdown.unwrap(),
+            "* Main:\n\n  ```rust include=../examples/main.rs:main\n  fn main() {\n      println!(\"Hi\");\n  }\n  ```\n"
+        );
+        assert!(warnings.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn code_span_ranges() {
+        let text = "`a` ``b ` c`` `d\ne` `f\n\ng` \\`h` ```";
+        let spans: Vec<&str> = code_spans(text)
+            .into_iter()
+            .map(|span| &text[span])
+            .collect();
+        // `f` isn't closed before the blank line, backslashes don't escape closing backticks
+        assert_eq!(spans, ["`a`", "``b ` c``", "`d\ne`", "` \\`"]);
+    }
+}
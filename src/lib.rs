@@ -14,6 +14,7 @@
 //! * [`cuteconfig.toml`](#cuteconfig)
 //!     * [`[misc]`](#config.misc)
 //!     * [`[config]`](#config.config)
+//!     * [`[diagrams]`](#config.diagrams)
 //! * [The front-matter](#frontmatter)
 //!     * [Example](#frontmatter.example)
 //! * [Templating](#templating)
@@ -21,11 +22,17 @@
 //!         * [Example](#templating.page.example)
 //!     * [`{{outer.*}}`](#templating.outer)
 //!         * [Example](#templating.outer.example)
+//!     * [Table of contents](#templating.toc)
+//!     * [Directives](#templating.directives)
 //! * [Source files](#sourcefiles)
+//!     * [Including files](#sourcefiles.include)
 //!     * [`SUMMARY.toml`](#sourcefiles.summary)
 //! * [Subcommands](#subcommands)
 //!     * [`init`](#subcommands.init)
 //!     * [`build`](#subcommands.build)
+//!     * [`check`](#subcommands.check)
+//!     * [`watch`](#subcommands.watch)
+//!     * [`serve`](#subcommands.serve)
 //!     * [`setup`](#subcommands.setup)
 //!     * [`update`](#subcommands.update)
 //!     * [`clean`](#subcommands.clean)
@@ -34,8 +41,14 @@
 //! * [Styles](#styles)
 //!     * [Using Sass](#styles.sass)
 //!     * [Not using Sass](#styles.css)
+//!     * [Overriding templates](#styles.templates)
 //! * [Routing](#routing)
 //! * [Preprocessors](#preprocessors)
+//!     * [Admonitions](#preprocessors.admonitions)
+//!         * [Custom kinds](#preprocessors.admonitions.kinds)
+//!     * [Plugins](#preprocessors.plugins)
+//!         * [WebAssembly plugins](#preprocessors.wasm)
+//! * [Using cuteness as a library](#library)
 //!
 //! ## `cuteconfig.toml` <a name="cuteconfig"></a>
 //!
@@ -45,7 +58,7 @@
 //! ```toml
 //! # cuteconfig.default.toml
 //! [misc]
-//! latex = true # Should cuteness build with KaTeX support?
+//! latex = true # Should cuteness render LaTeX equations?
 //! html_lang = "en" # What language should HTML use?
 //! syntax_highlighting = true
 //!
@@ -57,14 +70,39 @@
 //!
 //! This section handles miscellaneous settings, usually related to preprocessors and very case-specific tools.
 //!
-//! * `latex`: Enables LaTeX[^1] equations.
-//! * `html_lang`: Changes the starting `<html>` tag (e.g. *"es"* `<html lang="es">`).
-//! * `syntax_highlighting`: Enables syntax highlighting using [`highlight.js`](https://highlightjs.org/).
+//! * `latex`: Enables LaTeX[^1] equations: `$...$` and `\(...\)` in a line, `$$...$$` and `\[...\]` in their own line. They're rendered when building, so they show up without JavaScript or internet access. A `$` has to be right next to the equation and an equation can't contain another `$`, so prices are left alone (`$5 and $10. Then $x$` has one equation, `x`), `\$` is just a dollar sign, and nothing inside code is an equation. Invalid equations are shown with their error, and reported as warnings.
+//! * `[misc.latex_macros]` *(optional)*: Macros available in every equation, `#1`, `#2`... being their arguments (e.g. `RR = '\mathbb{R}'` for `\RR`, `norm = '\lVert #1 \rVert'` for `\norm{v}`).
+//! * `html_lang`: Changes the starting `<html>` tag (e.g. *"es"* `<html lang="es">`). It also selects the quotation marks used by the [preprocessors](#preprocessors) (e.g. `« »` for *"fr"*, `„ “` for *"de"*).
+//! * `syntax_highlighting`: Highlights fenced code blocks when building, using the language of their info string (` ```rust `); no JavaScript is needed to show them. Blocks without a language, or with one that isn't known, are left as they are.
+//! * `highlight_theme` *(optional)*: Colors used by `syntax_highlighting`, written to `styles/highlight.css`. It's the name of a built-in theme (`InspiredGitHub` by default, `Solarized (dark)`, `Solarized (light)`, `base16-eighties.dark`, `base16-mocha.dark`, `base16-ocean.dark` or `base16-ocean.light`) or the path to a `.tmTheme` file, relative to the project's root.
+//! * `theme` *(optional)*: Directory with templates and styles overriding the global ones (see [Overriding templates](#styles.templates)).
+//! * `[misc.quotes]` *(optional)*: Quotation marks overriding the ones of `html_lang`: `lang` (use another language's), `primary` (`["opening", "closing"]`, used for `"`) and `secondary` (used for `'`, nested quotes).
+//!
+//! ```toml
+//! [misc.quotes]
+//! primary = ["« ", " »"] # With (non-breaking) spaces
+//! ```
 //!
 //! ### `[config]` <a name="config.config"></a>
 //!
 //! This section is used to store user-provided configurations. It can store any [TOML value](https://toml.io/en/v1.0.0#keyvalue-pair) (*strings, integers, arrays...*).
 //!
+//! ### `[diagrams]` <a name="config.diagrams"></a>
+//!
+//! Renders the fenced code blocks of some languages with tools installed on your computer, when building: each `[diagrams.<language>]` has a `command` (paths are relative to the project's root) and its `args`. The block is written to the command's stdin, and the SVG it prints replaces the block (in a `<div class="diagram diagram-<language>">`), so readers don't need JavaScript to see it.
+//!
+//! ```toml
+//! [diagrams.dot] # Graphviz
+//! command = "dot"
+//! args = ["-Tsvg"]
+//!
+//! [diagrams.mermaid] # mermaid-cli
+//! command = "mmdc"
+//! args = ["--input", "-", "--output", "-", "--outputFormat", "svg"]
+//! ```
+//!
+//! Rendered diagrams are kept in the output directory (`.cuteness-diagrams`) and reused until the block, or its `command` or `args`, change (`build --no-cache` renders them again). If the command fails, the block is shown as code and the error is reported as a warning.
+//!
 //! ---
 //!
 //! All these sections can be used in your documents with `{{outer.*}}` (e.g. `{{outer.misc.html_lang}}`), we'll see more about templating in the next section.
@@ -76,6 +114,8 @@
 //! * `title`: The current page's title.
 //! * `pageconf` *(optional)*: User-provided page configuration (Key-value pairs).
 //! * `additional_css` *(optional)*: Additional CSS files needed to properly render the page. **(`index.css` is imported by default)**
+//! * `preprocessors` *(optional)*: Preprocessors to `enable` or `disable` for this page (see [Preprocessors](#preprocessors)).
+//! * `quotes` *(optional)*: Quotation marks for this page, same fields as [`[misc.quotes]`](#config.misc) (e.g. `quotes: { lang: de }` for a page in German).
 //!
 //! ### Example <a name="frontmatter.example"></a>
 //!
@@ -208,6 +248,48 @@
 //!
 //! ```
 //!
+//! ### Table of contents <a name="templating.toc"></a>
+//!
+//! Every heading gets an ID so you can link to its section (e.g. `introduction#getting-started`) and a `§` link to itself, shown when hovering it. The ID is the heading's text in lowercase, with spaces changed to `-` and punctuation removed (`## Getting started!` → `getting-started`); repeated ones get a number (`getting-started-1`). You can choose it yourself with `## Getting started {#start}`.
+//!
+//! `{{toc}}` shows the page's headings as nested lists of links; the default `page.html.hbs` uses it for its "On this page" panel. In a page, put it in an HTML block so it isn't wrapped in a paragraph:
+//!
+//! ```markdown
+//! <nav class="page-toc">{{toc}}</nav>
+//! ```
+//!
+//! The headings are also available as `{{page.toc}}`, nested by level, to show them your own way. Each one has an `id`, a `title` (already escaped, use `{{{this.title}}}`), its `level` (`1` to `6`) and its `children`. Write the links in HTML, Markdown links would escape the braces of `#{{this.id}}` before Handlebars sees them:
+//!
+//! ```handlebars
+//! <ul class="chapters">
+//! {{#each page.toc}}
+//! <li><a href="#{{this.id}}">{{{this.title}}}</a></li>
+//! {{/each}}
+//! </ul>
+//! ```
+//!
+//! ### Directives <a name="templating.directives"></a>
+//!
+//! Blocks between `::: <name>` and `:::` lines are rendered by the template `templates/directives/<name>.hbs` (looked up like [the other templates](#styles.templates)), so you can add tabs, cards, columns... to your project without touching cuteness. Attributes go after the name, as `key=value`, `key="a value with spaces"` or just `key` (meaning `true`). Directives can contain any Markdown and be nested (using more colons for the outer one makes it easier to read); `:::` lines in code blocks are left alone.
+//!
+//! ```markdown
+//! ::: card title="Getting started" wide
+//! Read the **introduction** first.
+//! :::
+//! ```
+//!
+//! The template gets the rendered HTML of the block in `{{{content}}}`, the attributes in `{{attrs.*}}`, the directive's `{{name}}`, `{{id}}` (a number, unique in the page) and the page's front-matter in `{{page.*}}`:
+//!
+//! ```handlebars
+//! <!-- templates/directives/card.hbs -->
+//! <div class="card{{#if attrs.wide}} wide{{/if}}" id="card-{{id}}">
+//!     <h3>{{attrs.title}}</h3>
+//!     {{{content}}}
+//! </div>
+//! ```
+//!
+//! Directives without a template, or that aren't closed, are reported as warnings.
+//!
 //! ## Source files <a name="sourcefiles"></a>
 //!
 //! A normal file tree looks something like this:
@@ -222,8 +304,29 @@
 //!
 //! All your Markdown files are located at the `src` directory; both `cuteconfig.toml` and `SUMMARY.toml` are located in the root directory. This is the default tree (generated by [`cuteness init`](#subcommands.init)) and it's the recommended way to start writing your contents.
 //!
+//! You can organize your files in directories, the output mirrors them: `src/guide/intro.md` is served at `/guide/intro`. Two pages can't share a route handler (e.g. `src/guide/intro.md` and `src/guide_intro.md` both get `guide_intro`), the build fails if they do. Templates get a `root` variable with the relative path back to the site's root (e.g. `../`), use it for links that should work from any page.
+//!
 //! When creating a new file, you'll have to start the file writing a [front-matter](#frontmatter) and then the contents of your file. As explained in [*Templating*](#templating), you can use [Handlebars templates](https://handlebarsjs.com/).
 //!
+//! ## Including files <a name="sourcefiles.include"></a>
+//!
+//! `{{#include path}}` is replaced by the contents of another file before the page is rendered, e.g. to share a part between pages. The path is relative to the file with the `{{#include}}`, and can point outside `src` but not outside the project: those `{{#include}}`s are left out (with a warning), so building a branch you don't trust can't publish other files of the machine. Add a selector after a `:` to include only part of the file:
+//!
+//! * `{{#include ../examples/main.rs:10}}`: line 10.
+//! * `{{#include ../examples/main.rs:10:30}}`: lines 10 to 30, `:10:` and `::30` leave one of the ends open.
+//! * `{{#include ../examples/main.rs:setup}}`: the lines between `ANCHOR: setup` and `ANCHOR_END: setup` (usually in comments). Lines with other anchors are left out.
+//!
+//! To show code examples that are kept (and tested) as real files, give a code block an `include=` option instead, with the same path and selector. The file becomes the block's content:
+//!
+//! ````markdown
+//! ```rust include=../examples/main.rs:setup
+//! ```
+//! ````
+//!
+//! `{{#include}}`s in code spans and code blocks are shown as they are, like the ones written `\{{#include path}}`, so pages can show the syntax. Included text isn't rendered by Handlebars either, `{{` in a code example is just text.
+//!
+//! Included files can include other files too. A missing file or anchor, or a file including itself (even through others), fails the build. The pages are re-rendered when an included file changes, both by [`build`](#subcommands.build) (the cache knows about included files) and [`watch`](#subcommands.watch).
+//!
 //! ## `SUMMARY.toml` <a name="sourcefiles.summary"></a>
 //!
 //! `SUMMARY.toml` is the file used to manage public links. The example `SUMMARY.toml` file (generated by [`cuteness init`](#subcommands.init)) looks like this:
@@ -277,15 +380,58 @@
 //!
 //! `cuteness build` is used to build the project, it will create an output directory containing the built version (using all your configurations) of your `src` directory. If there are `.sass` files in the directory `src/styles` it will also compile those.
 //!
+//! Builds are incremental: the output directory contains a build manifest (`.cuteness-cache.json`) with the hashes of every page's source and of the files every page depends on (`cuteconfig.toml`, `SUMMARY.toml`, the templates and the [plugins](#preprocessors.plugins)). Pages that didn't change since the last build aren't parsed nor rendered again. Use `cuteness build --no-cache` to render every page regardless.
+//!
+//! Pages are rendered in parallel, using one thread per CPU by default. Use `--jobs <N>` (or `-j <N>`) to change the number of threads.
+//!
+//! After building, every relative link and image (in the pages and in the sidebar) is checked against the generated pages and the files in the output's `static` directory, `#anchors` included. Links are taken from the rendered pages, so the ones in raw HTML, admonitions or added by preprocessors and plugins are checked too, and percent-encoded ones (`my%20page`) are decoded first. Broken links are reported as warnings with their file and line (when it's written in the source); use `--deny-broken-links` to make the build fail instead.
+//!
+//! ## `check` <a name="subcommands.check"></a>
+//!
+//! `cuteness check` validates the project without building it, reporting every problem at once. It checks what a build does before rendering the pages, and a bit more:
+//!
+//! * `cuteconfig.toml`, `SUMMARY.toml` or a page's front-matter can't be parsed.
+//! * Two pages would be written to the same file or have the same handler.
+//! * A page's `{{#include}}` points to a file or an anchor that doesn't exist.
+//! * A `url` in `SUMMARY.toml` doesn't match any file in `src`.
+//! * A page isn't in `SUMMARY.toml` (pages with parameters are ignored, as they're dynamic).
+//! * A page declares `params` that aren't in its path.
+//! * An `additional_css` entry doesn't point to any style.
+//! * A page enables or disables a preprocessor that doesn't exist.
+//!
+//! It exits with a non-zero code if any problem is found, so it can be used in CI.
+//!
+//! ## `watch` <a name="subcommands.watch"></a>
+//!
+//! `cuteness watch` builds the project (same options as [`build`](#subcommands.build)) and keeps watching `src`, `SUMMARY.toml`, `cuteconfig.toml` and the internal templates, rebuilding only what each change affects:
+//!
+//! * Editing a Markdown file re-renders only that page.
+//! * Editing `SUMMARY.toml` re-renders every page (the sidebar changed).
+//! * Editing a file in `src/styles` only compiles the styles.
+//! * Editing a file included by pages (with [`{{#include}}`](#sourcefiles.include)) re-renders those pages, even if it's outside `src`.
+//! * Editing `cuteconfig.toml` or a template rebuilds everything.
+//!
+//! ## `serve` <a name="subcommands.serve"></a>
+//!
+//! `cuteness serve` builds the project (same options as [`build`](#subcommands.build)) and serves the output's `static` directory at *http://localhost:8080/* without compiling the Rocket server. Every time a file in `src`, `cuteconfig.toml`, `SUMMARY.toml` or the internal templates changes, the affected parts are rebuilt (like [`watch`](#subcommands.watch) does) and the open pages are reloaded. It's the recommended way to preview your changes while writing.
+//!
 //! ## `setup` <a name="subcommands.setup"></a>
 //!
-//! `cuteness setup` is a one-time command, it's used to get all necessary template files from the web. **It requires internet connection**. You can think of it as an enhanced `git clone` that only clones necessary files.
+//! `cuteness setup` is an optional, one-time command, it's used to get the latest template files from the web. **It requires internet connection**. You can think of it as an enhanced `git clone` that only clones necessary files.
+//!
+//! The default templates and styles are embedded in the binary, so cuteness works out of the box (even without internet connection). Templates installed with this command take precedence over the embedded ones.
 //!
 //! **NOTE**: This command will create a directory called `cuteness-config` at your Cargo home (usually `~/.cargo/` on Unix systems) and store there all your internal configurations. (Do not edit manually.)
 //!
 //! ## `update` <a name="subcommands.update"></a>
 //!
-//! `cuteness update` will update the internal templates and styles to the latest version; you can think of it as an enhanced `git pull`.
+//! `cuteness update` will update the internal templates and styles to the latest version; you can think of it as an enhanced `git pull`. If they aren't installed yet, it works like [`setup`](#subcommands.setup).
+//!
+//! **NOTE**: The routing's `Cargo.toml` template is called `routing/Cargo.toml.hbs`, so running `cuteness update` with cuteness 0.9.10 or older removes the `routing/Cargo.toml` it needs, and its builds fail. To migrate:
+//!
+//! * Update the binary (`cargo install cuteness`) before running `cuteness update`. Until cuteness 0.11, `cuteness setup` and `cuteness update` keep writing `routing/Cargo.toml` too, for older binaries sharing the same Cargo home.
+//! * If the old binary already removed it, run `cuteness update` once with the new one.
+//! * If you override it in a project or theme, rename `templates/routing/Cargo.toml` to `templates/routing/Cargo.toml.hbs`: the old name is ignored.
 //!
 //! ## `clean` <a name="subcommands.clean"></a>
 //!
@@ -315,6 +461,17 @@
 //!
 //! Almost the same, just locate your `.css` files at `src/styles` and they will not get compiled, but only copied to the output directory.
 //!
+//! ## Overriding templates <a name="styles.templates"></a>
+//!
+//! Every template (`page.html.hbs`, `admonish.html.hbs`, `404.html`, `routing/src/main.rs.hbs`, `routing/Rocket.toml.hbs`, `routing/Cargo.toml.hbs`) and built-in style (`styles/index.css`) can be overridden per project. They're searched, by order, in:
+//!
+//! 1. The `templates` directory of your project (next to `cuteconfig.toml`).
+//! 2. The theme directory, if `theme` is set in [`[misc]`](#config.misc).
+//! 3. The templates installed with [`cuteness setup`](#subcommands.setup) (`<CARGO HOME>/cuteness-config/templates`).
+//! 4. The templates embedded in the binary.
+//!
+//! A directory only needs to contain the files it overrides, using the same layout (e.g. `templates/page.html.hbs` or `templates/styles/index.css`). Other files in a `styles` directory are copied to the output's `styles` directory too.
+//!
 //! # Routing <a name="routing"></a>
 //!
 //! When using `cuteness build`, an output directory containing some static files and a simple web-server will be generated which you can access by going to *http://localhost:8080/*
@@ -323,10 +480,134 @@
 //!
 //! # Preprocessors <a name="preprocessors"></a>
 //!
-//! The files content are preprocessed before being written, these preprocessors are used to change \"straight quotes\" to “curly quotes”, or to change emojicodes "`:cat:`" to actual emojis 🐱. These preprocessors are applied automatically and should not cause any problems.
+//! The files content are preprocessed before being written. These are the built-in preprocessors, in the order they run:
+//!
+//! * `admonish`: Turns ` ```admonish <kind> <title> ` code blocks and GitHub alerts (`> [!NOTE]`) into admonitions (see [Admonitions](#preprocessors.admonitions)).
+//! * `typography`: Changes \"straight quotes\" to “curly quotes” (and apostrophes, `don't` to `don’t`), `--` and `---` to en (–) and em (—) dashes and `...` to an ellipsis (…).
+//! * `emojis`: Changes emojicodes "`:cat:`" to actual emojis 🐱.
+//!
+//! They only change the text of your pages, never code (inline or in blocks), raw HTML or Handlebars expressions (`{{...}}`). Every preprocessor is enabled by default, the `[preprocessors]` table in `cuteconfig.toml` can disable or reorder them:
+//!
+//! ```toml
+//! [preprocessors]
+//! order = ["emojis"] # These run first, in this order; the rest run after them
+//! disable = ["typography"]
+//! ```
+//!
+//! A page can override it in its front-matter, disabling more preprocessors or enabling the ones disabled in `cuteconfig.toml`:
+//!
+//! ```yaml
+//! preprocessors:
+//!   enable: [typography]
+//!   disable: [emojis]
+//! ```
+//!
+//! ## Admonitions <a name="preprocessors.admonitions"></a>
+//!
+//! Admonitions are callouts (notes, warnings...) written as code blocks. Their body is Markdown, so it can contain lists, links, code blocks and even other admonitions (use a longer fence for the outer one):
+//!
+//! `````markdown
+//! ````admonish warning Read this first
+//! * Back up your files.
+//! * Run `cuteness check`.
+//!
+//! ```admonish collapsible tip class=small Why?
+//! Because [broken links](#subcommands.check) are sad.
+//! ```
+//! ````
+//! `````
+//!
+//! The info string is `admonish [options] <kind> [options] <title>`:
+//!
+//! * `kind`: `note`, `abstract` (or `summary`, `tldr`), `info` (`todo`), `tip` (`hint`, `important`), `success` (`check`, `done`), `question` (`help`, `faq`), `warning` (`caution`, `attention`), `failure` (`fail`, `missing`), `danger` (`error`), `bug`, `example`, `quote` (`cite`) or one declared in `[admonitions]`. Unknown kinds are shown as notes, with a warning.
+//! * `title` *(optional)*: The rest of the line (the kind's `title`, or the kind capitalized, if it's missing).
+//! * `collapsible`: Renders a `<details>` element, collapsed until it's clicked.
+//! * `open`: Same as `collapsible`, but initially open.
+//! * `class=<class>`: Adds classes to the admonition (separated by commas, e.g. `class=wide,red`).
+//!
+//! Options go before the title. The `admonish.html.hbs` template gets `kind`, `title`, `collapsible`, `open`, `class` and `content` (the rendered body).
+//!
+//! [GitHub's alerts](https://docs.github.com/en/get-started/writing-on-github/getting-started-with-writing-and-formatting-on-github/basic-writing-and-formatting-syntax#alerts) are admonitions too, so pages browsed on GitHub look right there as well. The first line of the blockquote is `[!<kind>]` (`NOTE`, `TIP`, `IMPORTANT`, `WARNING`, `CAUTION` or any other kind cuteness knows), the rest is the body:
+//!
+//! ```markdown
+//! > [!WARNING]
+//! > Back up your files first.
+//! ```
+//!
+//! They're rendered with the same template, without a title (so they get their kind's one). Blockquotes with an unknown kind are left alone.
+//!
+//! ### Custom kinds <a name="preprocessors.admonitions.kinds"></a>
+//!
+//! Declare your own kinds (or change the built-in ones) in `cuteconfig.toml`:
+//!
+//! ```toml
+//! [admonitions.security]
+//! color = "#e53935" # Any CSS colour (notes' blue by default)
+//! icon = "icons/shield.svg" # A file relative to the project's root, or inline `<svg ...>` (notes' pencil by default)
+//! title = "Security" # Used when an admonition doesn't have a title
+//!
+//! [admonitions.warning]
+//! title = "Careful!"
+//! ```
+//!
+//! Their styles are generated in `styles/admonition-kinds.css`, which the default `page.html.hbs` includes when `[admonitions]` isn't empty (the `admonitions` variable).
+//!
+//! When [using cuteness as a library](#library), you can add your own preprocessors by implementing [`Preprocessor`] and registering them with [`SiteBuilder::preprocessor`].
+//!
+//! ## Plugins <a name="preprocessors.plugins"></a>
+//!
+//! Plugins are preprocessors written in any language: executables or WebAssembly modules listed in `cuteconfig.toml` that get every page's Markdown (without its front-matter) before it's parsed. They run in the order they're listed, before the built-in preprocessors.
+//!
+//! ```toml
+//! [[plugin]]
+//! command = "./plugins/tickets.py" # Paths are relative to the project's root
+//! args = ["--repo", "blyxyas/cuteness"]
+//! timeout = 60 # Seconds it has to answer each message (default: 30)
+//! ```
+//!
+//! A plugin is started once per build and talks with cuteness in JSON, one message per line on its stdin and stdout (see [`plugins`] for every field):
+//!
+//! 1. cuteness sends `{"protocol": 1, "cuteness": "<version>"}` and the plugin answers with the protocol version it speaks, `{"protocol": 1}`.
+//! 2. For each page, cuteness sends `{"path": "src/my_file.md", "config": {<front-matter>}, "markdown": "..."}` and the plugin answers with `{"markdown": "..."}` (the new Markdown), `{"html": "..."}` (the page's final content, the next plugins and preprocessors are skipped) or `{"error": "..."}` (the build fails).
+//! 3. When the build is done, cuteness closes the plugin's stdin.
+//!
+//! Anything the plugin writes to stderr is shown as is. A plugin that doesn't answer within `timeout` seconds is stopped and the build fails. Changing a plugin's executable or module re-renders every page, like changing `cuteconfig.toml`.
+//!
+//! ### WebAssembly plugins <a name="preprocessors.wasm"></a>
+//!
+//! If `command` is a `.wasm` file, it's loaded by an embedded WebAssembly runtime instead of being executed (this needs the `wasm` feature, enabled by default). These plugins are sandboxed: they can't import anything, so they can't read or write files, use the network or see the environment. They also can't hang or exhaust the machine: a module running for too long (about a billion instructions per page) or using more than 256 MiB of memory fails the build. That makes them safe to run on content you don't trust, e.g. contributions built in CI.
+//!
+//! ```toml
+//! [[plugin]]
+//! command = "plugins/tickets.wasm"
+//! ```
+//!
+//! They exchange the same messages, through their memory: the module exports `memory`, `cuteness_alloc(len: i32) -> i32` (returns where cuteness can write a message of `len` bytes) and `cuteness_call(ptr: i32, len: i32) -> i64` (handles the message and returns where its answer is, the pointer in the high 32 bits and the length in the low ones).
+//!
+//! # Using cuteness as a library <a name="library"></a>
+//!
+//! The whole build pipeline is available from Rust through [`Site`], so you can build projects from your own tooling or tests. [`Site::builder`] takes the project's root (the directory with `cuteconfig.toml`) and the same options as `cuteness build`:
+//!
+//! ```no_run
+//! # fn main() -> cuteness::Result<()> {
+//! let output = cuteness::Site::builder("my-project")
+//!     .outdir("www")
+//!     .jobs(4)
+//!     .build()?;
+//!
+//! for route in &output.routes {
+//!     println!("{}", route.path);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With [`in_memory(true)`](SiteBuilder::in_memory), nothing is written to the output directory and every page's HTML is returned in [`Page::html`] instead.
+//!
+//! Failures are reported as an [`Error`] (e.g. [`Error::FrontMatter`] tells you which page has invalid front matter), nothing in the library panics on an invalid project.
 //!
 #![doc = document_features::document_features!()]
-//! [^1]: The tool specifically uses [KaTeX](https://katex.org/), specialized on equations.
+//! [^1]: Equations are converted to [MathML](https://developer.mozilla.org/docs/Web/MathML) with [`pulldown-latex`](https://github.com/carloskiki/pulldown-latex), which supports most of LaTeX's math commands.
 //!
 //! [^3]: `Handlebars-rs` uses the [Handlebars templating language](https://handlebarsjs.com/)
 //!
@@ -334,49 +615,61 @@
 //!
 //! [^5]: There are some ideas about porting the generated web-server to Rust. As the project isn't v1.0 yet, this may change in the future.
 
-use core::panic;
 use std::{
-    borrow::Cow,
     fs::{create_dir, remove_dir_all},
     path::{Path, PathBuf},
     process::Command,
 };
 
-use anyhow::{Context, Result};
-use emojis::get_by_shortcode;
+pub mod admonitions;
+mod cache;
+pub mod config;
+pub mod diagrams;
+pub mod directives;
+mod error;
+pub mod highlight;
+pub mod include;
+pub mod links;
+pub mod math;
+pub mod plugins;
+pub mod preprocessors;
+pub mod site;
+pub mod templates;
+pub mod toc;
+pub mod typography;
+
+pub use error::{Error, Result};
+pub use preprocessors::Preprocessor;
+pub use site::{BuildOutput, Page, Route, Site, SiteBuilder, Warning};
+
+use error::{run, Context};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 
 lazy_static! {
-    static ref REQUOTE: Regex = Regex::new("\"(.*?)\"").unwrap();
-    static ref REEMOJI: Regex = Regex::new(":(.*?):").unwrap();
-    pub static ref CONFIG_PATH: PathBuf = home::cargo_home()
-        .expect("Couldn't get Cargo home")
-        .join("cuteness-config");
     // Regex for < and >
     static ref RELTGT: Regex = Regex::new("<(.*?)>").unwrap();
 }
 
 const REPO_URL: &str = "https://github.com/blyxyas/cuteness.git";
 
-/// Replace straight quotes (") with curly quotes, U+201C (“) and U+201D (”)
-#[inline(always)]
-pub fn curly_quotes(content: &str) -> Cow<'_, str> {
-    REQUOTE.replace_all(content, "“$1”")
+/// Directory the templates are installed into by [`setup`] (`<Cargo home>/cuteness-config`).
+pub fn config_path() -> Result<PathBuf> {
+    Ok(home::cargo_home()
+        .context("Couldn't get Cargo home")?
+        .join("cuteness-config"))
 }
 
-/// Replaces all emojicodes (:cat:) to real emojis
-#[inline(always)]
-pub fn emojis(content: &str) -> String {
-    let mut result = <&str>::clone(&content).to_string();
-    for cap in REEMOJI.find_iter(content) {
-        if let Some(emoji) = get_by_shortcode(&content[cap.start() + 1..cap.end() - 1]) {
-            result = content.replace(cap.as_str(), emoji.as_str());
-        };
-    }
-    result
+/// What [`setup`] and [`check_for_updates`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Installation {
+    /// The templates were cloned
+    Installed,
+    /// The templates were already installed, nothing was done
+    AlreadyInstalled,
+    /// The templates were updated to the latest version
+    Updated,
 }
 
 #[inline]
@@ -390,129 +683,153 @@ pub fn params_in_path(path: &Path) -> Vec<String> {
     result
 }
 
+/// Clones the templates into [`config_path`] (a sparse checkout of cuteness' repository), does nothing if it already exists.
 #[inline]
-pub fn setup() {
-    if CONFIG_PATH.exists() {
-        return;
+pub fn setup() -> Result<Installation> {
+    let config_path = config_path()?;
+    if config_path.exists() {
+        return Ok(Installation::AlreadyInstalled);
     }
 
-    create_dir(CONFIG_PATH.as_path())
-        .unwrap_or_else(|e| panic!("Couldn't create directory `{}`: {e}", CONFIG_PATH.display()));
+    create_dir(&config_path)
+        .with_context(|| format!("Couldn't create directory `{}`", config_path.display()))?;
 
     // Initialize git repo
 
-    Command::new("git")
-        .current_dir(CONFIG_PATH.as_path())
-        .args(["init"])
-        .status()
-        .expect("Couldn't initialize repository");
+    run(Command::new("git").current_dir(&config_path).args(["init"]))?;
 
     // Add this repo as remote
 
-    Command::new("git")
-        .current_dir(CONFIG_PATH.as_path())
-        .args(["remote", "add", "origin", REPO_URL])
-        .status()
-        .expect("Couldn't add remote");
+    run(Command::new("git")
+        .current_dir(&config_path)
+        .args(["remote", "add", "origin", REPO_URL]))?;
 
     // Enable sparse checkout
 
-    Command::new("git")
-        .current_dir(CONFIG_PATH.as_path())
-        .args(["config", "core.sparseCheckout", "true"])
-        .status()
-        .expect("Couldn't enable `core.sparseCheckout`");
+    run(Command::new("git").current_dir(&config_path).args([
+        "config",
+        "core.sparseCheckout",
+        "true",
+    ]))?;
 
     // Add templates to sparse-checkout
 
-    std::fs::write(
-        CONFIG_PATH
-            .join(".git")
-            .join("info")
-            .join("sparse-checkout"),
-        "templates/*",
-    )
-    .unwrap_or_else(|e| {
-        panic!(
-            "Couldn't write to {}: {e}",
-            CONFIG_PATH
-                .join(".git")
-                .join("info")
-                .join("sparse-checkout")
-                .display()
-        )
-    });
-
-    Command::new("echo")
-        .current_dir(CONFIG_PATH.as_path())
-        .args(["templates/*", ">>", ".git/info/sparse-checkout"])
-        .status()
-        .expect("Couldn't add templates to `.git/info/sparse-checkout`");
+    let sparse_checkout = config_path
+        .join(".git")
+        .join("info")
+        .join("sparse-checkout");
+    std::fs::write(&sparse_checkout, "templates/*")
+        .with_context(|| format!("Couldn't write to {}", sparse_checkout.display()))?;
 
     // Pull
 
-    Command::new("git")
-        .current_dir(CONFIG_PATH.as_path())
-        .args(["pull", "--depth=1", "origin", "main"])
-        .status()
-        .expect("Couldn't pull");
+    run(Command::new("git").current_dir(&config_path).args([
+        "pull",
+        "--depth=1",
+        "origin",
+        "main",
+    ]))?;
 
-    println!("Cuteness was successfully configured!");
+    write_legacy_templates(&config_path)?;
+
+    Ok(Installation::Installed)
 }
 
+/// Pulls the latest templates into [`config_path`], running [`setup`] first if needed.
 #[inline]
-pub fn check_for_updates() {
-    Command::new("git")
-        .current_dir(CONFIG_PATH.as_path())
-        .args(["pull", "--depth=1", "origin", "main", "--rebase"])
-        .status()
-        .expect("Couldn't pull");
-
-    println!("Repository updated!");
+pub fn check_for_updates() -> Result<Installation> {
+    let config_path = config_path()?;
+    if !config_path.exists() {
+        return setup();
+    }
+
+    run(Command::new("git").current_dir(&config_path).args([
+        "pull",
+        "--depth=1",
+        "origin",
+        "main",
+        "--rebase",
+    ]))?;
+    write_legacy_templates(&config_path)?;
+
+    Ok(Installation::Updated)
+}
+
+/// Versions (`major.minor`) from which [`write_legacy_templates`] does nothing: binaries sharing the Cargo home have had a release to update by then.
+const LEGACY_TEMPLATES_UNTIL: (u64, u64) = (0, 11);
+
+/// Whether cuteness `version` still writes the legacy templates, see [`LEGACY_TEMPLATES_UNTIL`].
+fn writes_legacy_templates(version: &str) -> bool {
+    let mut parts = version
+        .split(['.', '-', '+'])
+        .map(|part| part.parse::<u64>().unwrap_or(0));
+    let major_minor = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    major_minor < LEGACY_TEMPLATES_UNTIL
+}
+
+/// Writes the templates cuteness 0.9.10 and older read under their old names (`routing/Cargo.toml`) into `config_path`, so updating the templates doesn't break them.
+fn write_legacy_templates(config_path: &Path) -> Result<()> {
+    if !writes_legacy_templates(env!("CARGO_PKG_VERSION")) {
+        return Ok(());
+    }
+
+    let routing = config_path.join("templates").join("routing");
+    std::fs::create_dir_all(&routing)
+        .with_context(|| format!("Couldn't create directory `{}`", routing.display()))?;
+
+    let path = routing.join("Cargo.toml");
+    std::fs::write(
+        &path,
+        templates::template("routing/Cargo.toml.hbs")?.as_bytes(),
+    )
+    .with_context(|| format!("Couldn't write to {}", path.display()))
 }
 
+/// Removes [`config_path`]
 #[inline]
-pub fn uninstall() {
-    let config_path = CONFIG_PATH.as_path();
+pub fn uninstall() -> Result<()> {
+    let config_path = config_path()?;
     if config_path.exists() {
-        remove_dir_all(config_path).unwrap_or_else(|e| {
-            panic!(
-                "Couldn't remove directory {}: {e}",
-                <&Path>::clone(&config_path).display()
-            )
-        });
+        remove_dir_all(&config_path)
+            .with_context(|| format!("Couldn't remove directory {}", config_path.display()))?;
     }
+    Ok(())
 }
 
-pub fn init() {
-    create_dir("src").unwrap_or_else(|e| panic!("Couldn't create directory 'src': {e}"));
+/// Creates a new project in the current directory, fails if there's already one.
+pub fn init() -> Result<()> {
+    for existing in ["src", "cuteconfig.toml", "SUMMARY.toml"] {
+        if Path::new(existing).exists() {
+            return Err(Error::AlreadyExists(PathBuf::from(existing)));
+        }
+    }
+
+    create_dir("src").context("Couldn't create directory 'src'")?;
 
     std::fs::write(
         "cuteconfig.toml",
         include_bytes!("../defaults/cuteconfig.toml"),
     )
-    .unwrap_or_else(|e| panic!("Couldn't create `cuteconfig.toml`: {e}"));
+    .context("Couldn't create `cuteconfig.toml`")?;
 
     std::fs::write("SUMMARY.toml", include_bytes!("../defaults/SUMMARY.toml"))
-        .unwrap_or_else(|e| panic!("Couldn't create `SUMMARY.md`: {e}"));
+        .context("Couldn't create `SUMMARY.toml`")?;
 
     std::fs::write(
         "src/introduction.md",
         include_bytes!("../defaults/introduction.md"),
     )
-    .unwrap_or_else(|e| panic!("Couldn't create `src/introduction.md`: {e}"));
+    .context("Couldn't create `src/introduction.md`")?;
+    Ok(())
 }
 
 /// As the feature "sass" is enabled, we're going to let Sass take care of the job.
 #[cfg(feature = "sass")]
 #[cold]
 #[inline(never)]
-pub fn compile_styles(outdir: &str, sass_bin: &str) -> Result<()> {
+pub fn compile_styles(indir: &str, outdir: &str, sass_bin: &str) -> Result<()> {
     // Compile custom styles
-    Command::new(sass_bin)
-        .arg(format!("src/styles:{}", &outdir))
-        .status()?;
-    Ok(())
+    run(Command::new(sass_bin).arg(format!("{}:{}", &indir, &outdir)))
 }
 
 /// As the feature "sass" isn't activated, all `.sass` (actually, all not `.css`) files are ignored. `*.css` files are copied to the output directory `styles` subdirectory.
@@ -554,61 +871,24 @@ pub fn compile_styles(indir: &str, outdir: &str) {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Default)]
+#[derive(Serialize, Deserialize, PartialEq, Default, Clone)]
 pub enum Method {
     #[default]
     GET,
     POST,
 }
 
-pub fn parse_admonish(admonish: &str, reg: &handlebars::Handlebars) -> Result<Option<String>> {
-    let split = admonish.split_whitespace().collect::<Vec<&str>>();
-    if split[0] != "admonish" {
-        return Ok(None);
-    };
-
-    let kind = if split.len() == 1 {
-        "note"
-    } else {
-        parse_admonishkind(split[1])
-    };
-
-    let title = if split.len() < 3 {
-        String::from("Note")
-    } else {
-        split[2..].join(" ")
-    };
-
-    let template = reg.render_template(
-        &std::fs::read_to_string(format!(
-            "{}/templates/admonish.html.hbs",
-            CONFIG_PATH.display(),
-        ))
-        .with_context(|| {
-            "Couldn't read file <CONFIG PATH>/templates/admonish.html.hbs".to_string()
-        })?,
-        &json!({
-            "title": title,
-            "kind": kind
-        }),
-    )?;
-
-    Ok(Some(template))
-}
-
-fn parse_admonishkind(s: &str) -> &str {
-    match s.to_lowercase().as_str() {
-        "info" => s,
-        "warning" => s,
-        "example" => s,
-        "tip" => s,
-        "bug" => s,
-        "tldr" => s,
-        "done" => s,
-        "help" => s,
-        "fail" => s,
-        "danger" => s,
-        "quote" => s,
-        _ => "note",
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_templates_are_temporary() {
+        assert!(writes_legacy_templates("0.9.10"));
+        assert!(writes_legacy_templates("0.10.3-beta.1"));
+        assert!(!writes_legacy_templates("0.11.0"));
+        assert!(!writes_legacy_templates("1.0.0"));
+        // Once this fails, remove `write_legacy_templates` and the migration note in `update`'s docs.
+        assert!(writes_legacy_templates(env!("CARGO_PKG_VERSION")));
     }
 }
@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::{Component, Path, PathBuf};
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{Event, Tag};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SummaryConfig;
+use crate::site::page_route;
+
+lazy_static! {
+    /// Anything that can be the target of a `#fragment`
+    static ref REANCHOR: Regex = Regex::new(r#"\s(?:id|name)\s*=\s*"([^"]*)""#).unwrap();
+    /// Links and images in HTML
+    static ref REURL: Regex = Regex::new(r#"\s(?:href|src)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+}
+
+/// Links found in a page and the anchors it defines, used to find broken links after a build.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PageLinks {
+    pub links: Vec<Link>,
+    pub anchors: BTreeSet<String>,
+}
+
+/// A link or image in a rendered page
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Link {
+    pub url: String,
+    /// Line in the source file (front matter included), `None` if it isn't written there (e.g. it comes from a plugin)
+    pub line: Option<usize>,
+}
+
+/// A link that doesn't point to any page, asset or anchor
+pub struct BrokenLink {
+    pub file: PathBuf,
+    pub line: Option<usize>,
+    pub url: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, "{line}:")?;
+        }
+        write!(f, " broken link `{}` ({})", self.url, self.reason)
+    }
+}
+
+/// Finds the links and images of a rendered page's events: Markdown links (from the page, admonitions' bodies or preprocessors) and the `href` / `src` attributes of its HTML (e.g. a plugin's).
+///
+/// `parsed` are the links found while parsing `markdown` (the page's Markdown) with their offset in it, `first_line` is the line (in the source file) where `markdown` starts. Links that weren't parsed from it are looked for in its text, they don't have a line if they aren't there.
+pub fn find_links(
+    events: &[Event],
+    markdown: &str,
+    first_line: usize,
+    parsed: &[(String, usize)],
+) -> Vec<Link> {
+    let mut links = Vec::new();
+    // Links are usually in the same order as in the Markdown, the next one is searched after the previous one.
+    let mut next = 0;
+    let mut cursor = 0;
+
+    for url in events.iter().flat_map(event_links) {
+        let offset = match parsed[next..].iter().position(|(parsed, _)| *parsed == url) {
+            Some(index) => {
+                next += index + 1;
+                Some(parsed[next - 1].1)
+            }
+            None => written_at(markdown, &url, cursor).or_else(|| written_at(markdown, &url, 0)),
+        };
+        if let Some(offset) = offset {
+            cursor = offset;
+        }
+
+        links.push(Link {
+            line: offset.map(|offset| first_line + markdown[..offset].matches('\n').count()),
+            url,
+        });
+    }
+
+    links
+}
+
+/// URLs of a link or image, or of the `href` / `src` attributes of some HTML.
+fn event_links(event: &Event) -> Vec<String> {
+    match event {
+        Event::Start(Tag::Link(_, url, _) | Tag::Image(_, url, _)) => vec![url.to_string()],
+        Event::Html(html) => REURL
+            .captures_iter(html)
+            .filter_map(|cap| cap.get(1).or_else(|| cap.get(2)))
+            .map(|url| url.as_str().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Offset of the first place (from `from`) where `url` is written as a link in `markdown`.
+fn written_at(markdown: &str, url: &str, from: usize) -> Option<usize> {
+    let rest = markdown.get(from..)?;
+    [
+        format!("]({url}"),
+        format!("]: {url}"),
+        format!("<{url}>"),
+        format!("\"{url}\""),
+        format!("'{url}'"),
+    ]
+    .iter()
+    .filter_map(|written| rest.find(written.as_str()))
+    .min()
+    .map(|offset| from + offset)
+}
+
+/// Decodes the percent-encoded bytes of an URL (`my%20page` is `my page`), `None` if they aren't valid UTF-8.
+pub fn percent_decode(url: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(url.len());
+    let mut rest = url.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Collects every `id` and `name` attribute of a rendered page.
+pub fn collect_anchors(html: &str) -> BTreeSet<String> {
+    REANCHOR
+        .captures_iter(html)
+        .map(|cap| cap[1].to_string())
+        .collect()
+}
+
+/// Checks every relative link (in the pages and the sidebar of the project at `root`) against the generated routes, the static assets in `<outdir>/static` and the anchors of the linked page.
+pub fn check_links(
+    root: &Path,
+    outdir: &Path,
+    pages: &BTreeMap<PathBuf, PageLinks>,
+    summary: &SummaryConfig,
+) -> Vec<BrokenLink> {
+    let anchors: BTreeMap<String, &BTreeSet<String>> = pages
+        .iter()
+        .map(|(path, links)| (page_route(path), &links.anchors))
+        .collect();
+
+    let mut broken = Vec::new();
+
+    for (path, links) in pages {
+        let route = page_route(path);
+        for link in &links.links {
+            if let Some(reason) = check_link(outdir, &anchors, &route, &link.url) {
+                broken.push(BrokenLink {
+                    file: path.clone(),
+                    line: link.line,
+                    url: link.url.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+
+    // * Sidebar =================================
+
+    let summary_source = read_to_string(root.join("SUMMARY.toml")).unwrap_or_default();
+    for map in &summary.map {
+        // Sidebar links are relative to the root
+        if let Some(reason) = check_link(outdir, &anchors, "", &map.url) {
+            broken.push(BrokenLink {
+                file: PathBuf::from("SUMMARY.toml"),
+                line: summary_source
+                    .lines()
+                    .position(|line| line.contains(&format!("\"{}\"", map.url)))
+                    .map(|line| line + 1),
+                url: map.url.clone(),
+                reason,
+            });
+        }
+    }
+
+    broken
+}
+
+/// Checks a link found in the page served at `route`, returns why it's broken (if it is).
+fn check_link(
+    outdir: &Path,
+    anchors: &BTreeMap<String, &BTreeSet<String>>,
+    route: &str,
+    url: &str,
+) -> Option<&'static str> {
+    // External links (`https://...`, `mailto:...`) and templated links can't be checked.
+    if is_external(url) || url.contains("{{") {
+        return None;
+    }
+
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let url = url.split('?').next().unwrap_or_default();
+    // `my%20page` is `my page`
+    let url = percent_decode(url).unwrap_or_else(|| url.to_string());
+    let fragment =
+        fragment.map(|fragment| percent_decode(fragment).unwrap_or_else(|| fragment.to_string()));
+
+    let target = if url.is_empty() {
+        route.to_string()
+    } else {
+        let Some(target) = resolve(route, &url) else {
+            return Some("points outside of the site");
+        };
+        target
+    };
+
+    if let Some(page_anchors) = anchors
+        .get(&target)
+        .or_else(|| anchors.get(target.strip_suffix(".html")?))
+    {
+        return match fragment {
+            Some(fragment) if !fragment.is_empty() && !page_anchors.contains(&fragment) => {
+                Some("no such anchor")
+            }
+            _ => None,
+        };
+    }
+
+    if outdir.join("static").join(&target).is_file() {
+        return None;
+    }
+
+    Some("no such page or file")
+}
+
+fn is_external(url: &str) -> bool {
+    url.starts_with("//")
+        || url
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains('/'))
+}
+
+/// Resolves `url` relative to the page served at `route`, returns `None` if it goes above the root.
+fn resolve(route: &str, url: &str) -> Option<String> {
+    let base = if url.starts_with('/') {
+        PathBuf::new()
+    } else {
+        Path::new(route)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in base.join(url.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::ParentDir if !resolved.pop() => return None,
+            _ => {}
+        }
+    }
+
+    Some(resolved.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pulldown_cmark::{LinkType, Parser};
+
+    use crate::site::markdown_options;
+
+    fn anchors<'a>(
+        pages: &[(&str, &'a BTreeSet<String>)],
+    ) -> BTreeMap<String, &'a BTreeSet<String>> {
+        pages
+            .iter()
+            .map(|(route, anchors)| (route.to_string(), *anchors))
+            .collect()
+    }
+
+    /// Finds the links of `markdown` (parsed like pages are) and of the `extra` events a preprocessor may add.
+    fn lines(markdown: &str, extra: Vec<Event>) -> Vec<(String, Option<usize>)> {
+        let mut parsed = Vec::new();
+        let mut events: Vec<Event> = Parser::new_ext(markdown, markdown_options())
+            .into_offset_iter()
+            .inspect(|(event, range)| {
+                if let Event::Start(Tag::Link(_, url, _) | Tag::Image(_, url, _)) = event {
+                    parsed.push((url.to_string(), range.start));
+                }
+            })
+            .map(|(event, _)| event)
+            .collect();
+        events.extend(extra);
+
+        find_links(&events, markdown, 1, &parsed)
+            .into_iter()
+            .map(|link| (link.url, link.line))
+            .collect()
+    }
+
+    #[test]
+    fn find_links_and_images() {
+        assert_eq!(
+            lines(
+                "[a](a)\n\nText ![b](b.png)\nand [c][ref]\n\n[ref]: c",
+                Vec::new()
+            ),
+            [
+                ("a".to_string(), Some(1)),
+                ("b.png".to_string(), Some(3)),
+                ("c".to_string(), Some(4))
+            ]
+        );
+        assert!(lines("`[a](a)`\n\n```\n[b](b)\n```", Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn find_links_added_by_preprocessors() {
+        // Like an admonition's body, parsed by its preprocessor
+        let link = Event::Start(Tag::Link(LinkType::Inline, "a".into(), "".into()));
+        assert_eq!(
+            lines(
+                "Intro [first](b)\n\n```admonish info\nText\nA [link](a)\n```\n",
+                vec![link.clone()]
+            ),
+            [("b".to_string(), Some(1)), ("a".to_string(), Some(5))]
+        );
+        // Not in the Markdown
+        assert_eq!(lines("Text", vec![link]), [("a".to_string(), None)]);
+    }
+
+    #[test]
+    fn find_links_in_html() {
+        assert_eq!(
+            lines(
+                "Text\n\n<a href=\"raw\">x</a> <img src='img.png'>\n",
+                vec![Event::Html(
+                    r#"<p><a class="x" href="plugin#top">Plugin</a></p>"#.into()
+                )]
+            ),
+            [
+                ("raw".to_string(), Some(3)),
+                ("img.png".to_string(), Some(3)),
+                ("plugin#top".to_string(), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("my%20page").as_deref(), Some("my page"));
+        assert_eq!(percent_decode("caf%C3%A9").as_deref(), Some("café"));
+        assert_eq!(percent_decode("100%").as_deref(), Some("100%"));
+        assert_eq!(percent_decode("%zz%2").as_deref(), Some("%zz%2"));
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn resolve_relative_to_the_page() {
+        assert_eq!(
+            resolve("guide/intro", "setup").as_deref(),
+            Some("guide/setup")
+        );
+        assert_eq!(resolve("guide/intro", "../index").as_deref(), Some("index"));
+        assert_eq!(resolve("guide/intro", "/index").as_deref(), Some("index"));
+        assert_eq!(
+            resolve("intro", "./img/a.png").as_deref(),
+            Some("img/a.png")
+        );
+        assert_eq!(resolve("intro", "../index"), None);
+    }
+
+    #[test]
+    fn external_links() {
+        assert!(is_external("https://example.com"));
+        assert!(is_external("mailto:someone@example.com"));
+        assert!(is_external("//example.com/x.css"));
+        assert!(!is_external("guide/intro"));
+        assert!(!is_external("a/b:c"));
+    }
+
+    #[test]
+    fn anchors_are_ids_and_names() {
+        let html = r#"<h2 id="setup">Setup</h2><a name="old"></a><div class="id"></div>"#;
+        assert_eq!(
+            collect_anchors(html),
+            BTreeSet::from(["setup".to_string(), "old".to_string()])
+        );
+    }
+
+    #[test]
+    fn check_links_to_pages_and_anchors() {
+        let intro = BTreeSet::from(["setup".to_string()]);
+        let index = BTreeSet::new();
+        let page = BTreeSet::from(["sé".to_string()]);
+        let anchors = anchors(&[
+            ("guide/intro", &intro),
+            ("guide/my page", &page),
+            ("index", &index),
+        ]);
+        let outdir = Path::new("/nonexistent");
+        let check = |url| check_link(outdir, &anchors, "guide/intro", url);
+
+        assert_eq!(check("../index"), None);
+        assert_eq!(check("intro.html#setup"), None);
+        assert_eq!(check("#setup"), None);
+        assert_eq!(check("#"), None);
+        assert_eq!(check("intro?x=1#setup"), None);
+        assert_eq!(check("#teardown"), Some("no such anchor"));
+        assert_eq!(check("missing"), Some("no such page or file"));
+        assert_eq!(check("../../index"), Some("points outside of the site"));
+        assert_eq!(check("https://example.com/missing"), None);
+        assert_eq!(check("{{outer.config.url}}"), None);
+        assert_eq!(check("my%20page#s%C3%A9"), None);
+        assert_eq!(check("my%20page#missing"), Some("no such anchor"));
+    }
+
+    #[test]
+    fn check_links_to_static_files() {
+        let outdir = std::env::temp_dir().join(format!("cuteness-links-{}", std::process::id()));
+        std::fs::create_dir_all(outdir.join("static/img")).unwrap();
+        std::fs::write(outdir.join("static/img/cat.png"), "").unwrap();
+
+        assert_eq!(
+            check_link(&outdir, &BTreeMap::new(), "index", "img/cat.png"),
+            None
+        );
+        assert_eq!(
+            check_link(&outdir, &BTreeMap::new(), "index", "img/dog.png"),
+            Some("no such page or file")
+        );
+        std::fs::remove_dir_all(outdir).unwrap();
+    }
+}
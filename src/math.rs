@@ -0,0 +1,463 @@
+//! Math rendered when building (`latex = true`): `$…$`, `$$…$$`, `\(…\)` and `\[…\]` are converted to MathML with [`pulldown_latex`], so equations show up without JavaScript.
+//!
+//! Equations are found in the Markdown before parsing it ([`mark`]), as Markdown would take `\(` for an escaped parenthesis and `a*b*c` for emphasis. They're replaced by a token made of private-use characters (plain text for Markdown, so it doesn't change how the paragraph is parsed, unlike an HTML comment at the start of a line), followed by as many lines as the equation had so line numbers don't change. Once the page is parsed, the tokens are replaced by their MathML ([`render`]).
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Parser, Tag};
+use pulldown_latex::config::DisplayMode;
+use pulldown_latex::{push_mathml, RenderConfig, Storage};
+
+use crate::site::markdown_options;
+
+/// Starts an equation's token, followed by its number and [`TOKEN_END`]
+const TOKEN_START: char = '\u{E000}';
+const TOKEN_END: char = '\u{E001}';
+/// Starts the lines standing for an equation's line breaks (so they aren't blank lines, which would end the paragraph)
+const LINE: char = '\u{E002}';
+
+/// An equation found by [`mark`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Equation {
+    pub latex: String,
+    /// `$$…$$` and `\[…\]`, shown in their own line
+    pub display: bool,
+}
+
+/// Replaces the equations of a page (except in code) by tokens, returns the new Markdown and the equations, by number.
+///
+/// Like in Pandoc, `$` only opens an equation if it's followed by a non-space character (and not by a number and a space, like `$5 and`), and only closes it if it's preceded by one and not followed by a digit. An equation can't contain another `$`, so prices (`$5 and $10. Then $x$`) are left alone. `\$` is a dollar sign.
+pub fn mark(markdown: &str) -> (String, Vec<Equation>) {
+    let mut result = String::with_capacity(markdown.len());
+    let mut equations = Vec::new();
+
+    let mut start = 0;
+    for range in code(markdown)
+        .into_iter()
+        .chain(std::iter::once(markdown.len()..markdown.len()))
+    {
+        if range.start < start {
+            continue;
+        }
+        mark_text(&markdown[start..range.start], &mut result, &mut equations);
+        result.push_str(&markdown[range.clone()]);
+        start = range.end;
+    }
+
+    (result, equations)
+}
+
+/// Ranges of the code spans and code blocks, except the body of admonitions (which is Markdown).
+fn code(markdown: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut parser = Parser::new_ext(markdown, markdown_options()).into_offset_iter();
+
+    while let Some((event, range)) = parser.next() {
+        match event {
+            Event::Code(_) => ranges.push(range),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let admonition =
+                    matches!(&kind, CodeBlockKind::Fenced(info) if info.starts_with("admonish"));
+                let mut body: Option<Range<usize>> = None;
+                for (event, text) in parser.by_ref() {
+                    match event {
+                        Event::End(Tag::CodeBlock(_)) => break,
+                        Event::Text(_) => {
+                            body = Some(body.map_or(text.clone(), |body| body.start..text.end))
+                        }
+                        _ => {}
+                    }
+                }
+
+                match body.filter(|_| admonition) {
+                    Some(body) => {
+                        ranges.push(range.start..body.start);
+                        ranges.extend(
+                            code(&markdown[body.clone()])
+                                .into_iter()
+                                .map(|code| code.start + body.start..code.end + body.start),
+                        );
+                        ranges.push(body.end..range.end);
+                    }
+                    None => ranges.push(range),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ranges
+}
+
+/// Marks the equations of some text without code.
+fn mark_text(text: &str, result: &mut String, equations: &mut Vec<Equation>) {
+    let mut rest = text;
+
+    while let Some(i) = rest.find(['$', '\\']) {
+        result.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        // (LaTeX, display, length with the delimiters)
+        let found = if let Some(after) = rest.strip_prefix("$$") {
+            after.find("$$").map(|end| (&after[..end], true, end + 4))
+        } else if let Some(after) = rest.strip_prefix('$') {
+            inline_end(after).map(|end| (&after[..end], false, end + 2))
+        } else if let Some(after) = rest.strip_prefix("\\(") {
+            after.find("\\)").map(|end| (&after[..end], false, end + 4))
+        } else if let Some(after) = rest.strip_prefix("\\[") {
+            after.find("\\]").map(|end| (&after[..end], true, end + 4))
+        } else {
+            None
+        };
+
+        match found.filter(|(latex, ..)| !latex.trim().is_empty()) {
+            Some((latex, display, len)) => {
+                result.push_str(&format!(
+                    "{TOKEN_START}{}{TOKEN_END}{}",
+                    equations.len(),
+                    format!("\n{LINE}").repeat(latex.matches('\n').count())
+                ));
+                equations.push(Equation {
+                    latex: latex.to_string(),
+                    display,
+                });
+                rest = &rest[len..];
+            }
+            None => {
+                // `\` escapes the next character (e.g. `\$`), an unclosed `$$` isn't two `$`.
+                let len = match rest.strip_prefix('\\') {
+                    Some(escaped) => 1 + escaped.chars().next().map_or(0, char::len_utf8),
+                    None if rest.starts_with("$$") => 2,
+                    None => 1,
+                };
+                result.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+}
+
+/// Finds the `$` closing an inline equation, in what follows the opening one. `None` if it isn't one.
+fn inline_end(after: &str) -> Option<usize> {
+    let number = after.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ',');
+    let is_price = number.len() < after.len() && number.starts_with(char::is_whitespace);
+    if after.starts_with(char::is_whitespace) || is_price {
+        return None;
+    }
+
+    let mut escaped = false;
+    for (i, c) in after.char_indices() {
+        match c {
+            '\\' => {
+                escaped = !escaped;
+                continue;
+            }
+            '$' if !escaped => {
+                let closes = !after[..i].ends_with(char::is_whitespace)
+                    && !after[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+                // Another `$` that can't close it: it's a dollar sign, and so is the opening one.
+                return closes.then_some(i);
+            }
+            // Equations don't go past the end of a paragraph.
+            '\n' if after[i + 1..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with('\n') =>
+            {
+                return None;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+
+    None
+}
+
+/// Replaces the equations marked by [`mark`] in a parsed page by their MathML (as HTML events). `macros` (`[misc.latex_macros]`) are available in every equation; invalid equations are shown with their error, and reported with `warn`.
+pub fn render<'a>(
+    events: Vec<Event<'a>>,
+    equations: &[Equation],
+    macros: &BTreeMap<String, String>,
+    warn: &mut impl FnMut(String),
+) -> Vec<Event<'a>> {
+    if equations.is_empty() {
+        return events;
+    }
+
+    let prelude = prelude(macros);
+    let mut result = Vec::with_capacity(events.len());
+
+    for event in events {
+        match event {
+            Event::Text(text) if text.contains([TOKEN_START, LINE]) => {
+                let mut rest = text.as_ref();
+                while let Some((before, equation, after)) = next_token(rest, equations) {
+                    push_text(&mut result, before);
+                    result.push(Event::Html(mathml(equation, &prelude, warn).into()));
+                    rest = after;
+                }
+                push_text(&mut result, rest);
+            }
+            // Equations in raw HTML (e.g. `<div>$x$</div>`)
+            Event::Html(html) if html.contains([TOKEN_START, LINE]) => {
+                let mut replaced = String::with_capacity(html.len());
+                let mut rest = html.as_ref();
+                while let Some((before, equation, after)) = next_token(rest, equations) {
+                    replaced.push_str(before);
+                    replaced.push_str(&mathml(equation, &prelude, warn));
+                    rest = after;
+                }
+                replaced.push_str(rest);
+                result.push(Event::Html(replaced.replace(LINE, "").into()));
+            }
+            event => result.push(event),
+        }
+    }
+
+    result
+}
+
+/// Finds the first equation token in `text`: returns what's before it, its equation and what's after it.
+fn next_token<'t, 'e>(
+    text: &'t str,
+    equations: &'e [Equation],
+) -> Option<(&'t str, &'e Equation, &'t str)> {
+    let mut from = 0;
+    loop {
+        let start = from + text[from..].find(TOKEN_START)?;
+        let after = &text[start + TOKEN_START.len_utf8()..];
+        let found = after
+            .split_once(TOKEN_END)
+            .and_then(|(number, rest)| Some((equations.get(number.parse::<usize>().ok()?)?, rest)));
+        match found {
+            Some((equation, rest)) => return Some((&text[..start], equation, rest)),
+            None => from = start + TOKEN_START.len_utf8(),
+        }
+    }
+}
+
+/// Adds some text, without the characters standing for line breaks.
+fn push_text<'a>(events: &mut Vec<Event<'a>>, text: &str) {
+    let text = text.replace(LINE, "");
+    if !text.is_empty() {
+        events.push(Event::Text(CowStr::from(text)));
+    }
+}
+
+/// Defines the macros with `\newcommand`, their number of arguments is the highest `#n` they use.
+fn prelude(macros: &BTreeMap<String, String>) -> String {
+    macros
+        .iter()
+        .map(|(name, definition)| {
+            let arguments = (1..=9)
+                .rev()
+                .find(|n| definition.contains(&format!("#{n}")))
+                .unwrap_or(0);
+            format!(
+                "\\newcommand{{\\{}}}[{arguments}]{{{definition}}}",
+                name.trim_start_matches('\\')
+            )
+        })
+        .collect()
+}
+
+fn mathml(equation: &Equation, prelude: &str, warn: &mut impl FnMut(String)) -> String {
+    let latex = equation.latex.trim();
+    let input = format!("{prelude}{latex}");
+    let storage = Storage::new();
+    let events: Vec<_> = pulldown_latex::Parser::new(&input, &storage).collect();
+
+    if let Some(Err(error)) = events.iter().find(|event| event.is_err()) {
+        let error = error.to_string();
+        warn(format!(
+            "invalid math `{latex}`: {}",
+            error.lines().next().unwrap_or_default()
+        ));
+    }
+
+    let config = RenderConfig {
+        display_mode: match equation.display {
+            true => DisplayMode::Block,
+            false => DisplayMode::Inline,
+        },
+        // Keeps the source, for screen readers and copying.
+        annotation: Some(latex),
+        ..RenderConfig::default()
+    };
+    let mut mathml = String::new();
+    push_mathml(&mut mathml, events.into_iter(), config)
+        .expect("writing to a `String` doesn't fail");
+
+    escape_operators(&mathml)
+}
+
+/// Escapes the `<` and `&` written as is by the renderer (e.g. `<mo><</mo>`).
+fn escape_operators(mathml: &str) -> String {
+    let mut result = String::with_capacity(mathml.len());
+
+    for (i, c) in mathml.char_indices() {
+        let next = &mathml[i + c.len_utf8()..];
+        match c {
+            '<' if !next.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') => {
+                result.push_str("&lt;")
+            }
+            '&' if !next.split_once(';').is_some_and(|(entity, _)| {
+                !entity.is_empty()
+                    && entity.len() <= 32
+                    && entity
+                        .trim_start_matches('#')
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric())
+            }) =>
+            {
+                result.push_str("&amp;")
+            }
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::html;
+
+    use super::*;
+
+    /// Renders `markdown` with equations, returns the HTML and the warnings.
+    fn render_with(markdown: &str, macros: &BTreeMap<String, String>) -> (String, Vec<String>) {
+        let mut warnings = Vec::new();
+        let (markdown, equations) = mark(markdown);
+        let events = Parser::new_ext(&markdown, markdown_options()).collect();
+        let events = render(events, &equations, macros, &mut |m| warnings.push(m));
+        let mut html = String::new();
+        html::push_html(&mut html, events.into_iter());
+        (html, warnings)
+    }
+
+    fn render_html(markdown: &str) -> String {
+        render_with(markdown, &BTreeMap::new()).0
+    }
+
+    #[test]
+    fn equation_at_the_start_of_a_paragraph() {
+        let html = render_html("$x$ is a **variable** and\nso is $y$.");
+        assert!(html.starts_with("<p><math"), "{html}");
+        assert!(html.contains("<strong>variable</strong>"));
+        assert_eq!(html.matches("<p>").count(), 1);
+        assert_eq!(html.matches("<math").count(), 2);
+        assert!(!html.contains(TOKEN_START));
+    }
+
+    #[test]
+    fn equation_in_a_paragraph() {
+        let html = render_html("The area is $\\pi r^2$, *roughly*.");
+        assert!(html.starts_with("<p>The area is <math"), "{html}");
+        assert!(html.contains("<mi>π</mi>"));
+        assert!(html.contains("</math>, <em>roughly</em>.</p>"));
+    }
+
+    #[test]
+    fn display_equation() {
+        let (markdown, equations) = mark("Before\n\n$$\na + b\n$$\n\nAfter [link](x)");
+        assert!(equations[0].display);
+        // Lines after the equation keep their number.
+        assert_eq!(markdown.lines().count(), 7);
+
+        let html = render_html("Before\n\n$$\na + b\n$$\n\nAfter");
+        assert!(html.contains("<math display=\"block\""), "{html}");
+        assert!(html.contains("<p>After</p>"));
+        assert!(!html.contains(LINE));
+    }
+
+    #[test]
+    fn other_delimiters() {
+        let (_, equations) = mark("\\(a\\) and \\[b\\]");
+        assert_eq!(
+            equations,
+            [
+                Equation {
+                    latex: "a".to_string(),
+                    display: false
+                },
+                Equation {
+                    latex: "b".to_string(),
+                    display: true
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn prices_and_escaped_dollars() {
+        assert!(mark("It costs $5 and $10.").1.is_empty());
+        assert!(mark("It costs \\$5, or $ 3 $.").1.is_empty());
+        assert!(mark("From $1,000.50 to $2 000").1.is_empty());
+
+        let (markdown, equations) = mark("Price is $5 and $10. Math: $x^2$");
+        assert_eq!(
+            equations,
+            [Equation {
+                latex: "x^2".to_string(),
+                display: false
+            }]
+        );
+        assert!(markdown.starts_with("Price is $5 and $10. Math: \u{E000}"));
+        let (html, warnings) = render_with("Price is $5 and $10. Math: $x^2$", &BTreeMap::new());
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(
+            html.starts_with("<p>Price is $5 and $10. Math: <math display=\"inline\">"),
+            "{html}"
+        );
+
+        let (_, equations) = mark("$a$ costs $3 each, $b$ is $2x$ and $\\$$");
+        let latex: Vec<_> = equations.iter().map(|e| e.latex.as_str()).collect();
+        assert_eq!(latex, ["a", "b", "2x", "\\$"]);
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let (markdown, equations) = mark("`$a$` and\n\n```\n$$b$$\n```\n");
+        assert!(equations.is_empty());
+        assert_eq!(markdown, "`$a$` and\n\n```\n$$b$$\n```\n");
+    }
+
+    #[test]
+    fn admonition_bodies_are_markdown() {
+        let (_, equations) = mark("```admonish info\n$a$ and `$b$`\n```\n");
+        assert_eq!(equations.len(), 1);
+        assert_eq!(equations[0].latex, "a");
+    }
+
+    #[test]
+    fn equations_in_raw_html() {
+        let html = render_html("<div>$x$</div>\n");
+        assert!(html.starts_with("<div><math"), "{html}");
+    }
+
+    #[test]
+    fn macros() {
+        let macros = BTreeMap::from([("RR".to_string(), "\\mathbb{R}".to_string())]);
+        let (html, warnings) = render_with("$x \\in \\RR$", &macros);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(html.contains("ℝ"), "{html}");
+    }
+
+    #[test]
+    fn invalid_equations_warn() {
+        let (_, warnings) = render_with("$\\frac{a$", &BTreeMap::new());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("invalid math `\\frac{a`"));
+    }
+
+    #[test]
+    fn operators_are_escaped() {
+        let html = render_html("$a < b$");
+        assert!(html.contains("&lt;"), "{html}");
+        assert!(!html.contains("<mo><</mo>"));
+    }
+}
@@ -0,0 +1,526 @@
+//! External plugins (`[[plugin]]` in `cuteconfig.toml`): executables or WebAssembly modules that transform every page's Markdown before it's parsed.
+//!
+//! A plugin is started once per build and exchanges JSON messages with cuteness:
+//!
+//! 1. cuteness sends a [`Handshake`], the plugin answers with a [`HandshakeReply`] (its protocol version must be [`PROTOCOL_VERSION`]).
+//! 2. For every page, cuteness sends a [`Request`] and the plugin answers with a [`Response`].
+//!
+//! Executables are started in the project's root and get JSON lines (one JSON object per line) on their stdin, they answer on their stdout. Anything written to stderr is shown to the user. They have `timeout` seconds ([`DEFAULT_TIMEOUT`] by default) to answer each message, or they're killed and the build fails. When the build is done, cuteness closes their stdin: they should exit.
+//!
+//! WebAssembly modules (`.wasm` files, with the `wasm` feature) are sandboxed: they can't import anything, so they can't touch the filesystem, the network or the environment. They can run about a billion instructions for each message and use up to 256 MiB of memory, so a plugin stuck in a loop or allocating without end fails the build instead of hanging it. They must export:
+//!
+//! * `memory`: their linear memory.
+//! * `cuteness_alloc(len: i32) -> i32`: allocates `len` bytes for a message and returns a pointer to them (the message is theirs, they can free it when they want).
+//! * `cuteness_call(ptr: i32, len: i32) -> i64`: handles the message at `ptr` and returns the answer's pointer (in the high 32 bits) and length (in the low 32 bits). The answer has to stay valid until the next call.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{PageConfig, PluginConfig};
+use crate::{Error, Result};
+
+/// Version of the protocol, it changes every time a message does.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Time an executable has to answer each message, unless its `timeout` says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// First message sent to a plugin
+#[derive(Serialize, Deserialize)]
+pub struct Handshake {
+    pub protocol: u32,
+    /// cuteness' version
+    pub cuteness: String,
+}
+
+/// Answer to the [`Handshake`]
+#[derive(Serialize, Deserialize)]
+pub struct HandshakeReply {
+    pub protocol: u32,
+}
+
+/// A page to transform
+#[derive(Serialize, Deserialize)]
+pub struct Request {
+    /// Source file, relative to the project's root
+    pub path: PathBuf,
+    pub config: PageConfig,
+    /// The page's Markdown, without the front matter (and already transformed by the previous plugins)
+    pub markdown: String,
+}
+
+/// A transformed page, with one of the fields set
+#[derive(Serialize, Deserialize, Default)]
+pub struct Response {
+    /// New Markdown for the page
+    pub markdown: Option<String>,
+    /// Final HTML for the page's content: it isn't parsed nor preprocessed, and the next plugins aren't run.
+    pub html: Option<String>,
+    /// The page couldn't be transformed, the build fails with this message.
+    pub error: Option<String>,
+}
+
+/// A running plugin
+pub struct Plugin {
+    command: String,
+    /// Pages are rendered in parallel, but a plugin handles them one by one.
+    transport: Mutex<Transport>,
+}
+
+/// How messages get to a plugin
+enum Transport {
+    Process(Process),
+    #[cfg(feature = "wasm")]
+    Wasm(Box<wasm::Module>),
+}
+
+impl Plugin {
+    /// Starts the plugin (in `root`) and checks its protocol version.
+    pub fn start(root: &Path, config: &PluginConfig) -> Result<Self> {
+        let error = |message| Error::Plugin {
+            command: config.command.clone(),
+            message,
+        };
+
+        let transport = if Path::new(&config.command)
+            .extension()
+            .is_some_and(|ext| ext == "wasm")
+        {
+            #[cfg(feature = "wasm")]
+            {
+                Transport::Wasm(Box::new(
+                    wasm::Module::load(&root.join(&config.command), wasm::FUEL).map_err(error)?,
+                ))
+            }
+            #[cfg(not(feature = "wasm"))]
+            return Err(error(
+                "WebAssembly plugins need the `wasm` feature".to_string(),
+            ));
+        } else {
+            Transport::Process(Process::start(root, config).map_err(error)?)
+        };
+
+        let plugin = Self {
+            command: config.command.clone(),
+            transport: Mutex::new(transport),
+        };
+
+        let reply: HandshakeReply = plugin.call(&Handshake {
+            protocol: PROTOCOL_VERSION,
+            cuteness: env!("CARGO_PKG_VERSION").to_string(),
+        })?;
+        if reply.protocol != PROTOCOL_VERSION {
+            return Err(plugin.error(format!(
+                "speaks protocol version {}, cuteness speaks version {PROTOCOL_VERSION}",
+                reply.protocol
+            )));
+        }
+
+        Ok(plugin)
+    }
+
+    /// Sends a page to the plugin.
+    pub fn run(&self, path: &Path, config: &PageConfig, markdown: String) -> Result<Response> {
+        let response: Response = self.call(&Request {
+            path: path.to_path_buf(),
+            config: config.clone(),
+            markdown,
+        })?;
+
+        if let Some(error) = &response.error {
+            return Err(self.error(format!("`{}`: {error}", path.display())));
+        }
+        if response.markdown.is_none() && response.html.is_none() {
+            return Err(self.error(format!(
+                "`{}`: the response has neither `markdown` nor `html`",
+                path.display()
+            )));
+        }
+        Ok(response)
+    }
+
+    fn call<T: DeserializeOwned>(&self, message: &impl Serialize) -> Result<T> {
+        let message = serde_json::to_string(message)
+            .map_err(|e| self.error(format!("couldn't serialize message: {e}")))?;
+        let answer = match &mut *self
+            .transport
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+        {
+            Transport::Process(process) => process.call(&message),
+            #[cfg(feature = "wasm")]
+            Transport::Wasm(module) => module.call(&message),
+        }
+        .map_err(|e| self.error(e))?;
+
+        serde_json::from_str(&answer).map_err(|e| self.error(format!("invalid answer: {e}")))
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Plugin {
+            command: self.command.clone(),
+            message,
+        }
+    }
+}
+
+/// An executable plugin, talking through its stdin and stdout
+struct Process {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// Lines of its stdout, read by another thread so waiting for them can time out
+    answers: Receiver<io::Result<String>>,
+    timeout: Duration,
+}
+
+impl Process {
+    fn start(root: &Path, config: &PluginConfig) -> Result<Self, String> {
+        let timeout = match config.timeout {
+            Some(seconds) => Duration::try_from_secs_f64(seconds)
+                .map_err(|e| format!("invalid timeout `{seconds}`: {e}"))?,
+            None => DEFAULT_TIMEOUT,
+        };
+
+        // Paths (not bare command names) are relative to the project.
+        let program = if config.command.contains(['/', '\\']) {
+            root.join(&config.command)
+        } else {
+            PathBuf::from(&config.command)
+        };
+
+        let mut child = Command::new(program)
+            .args(&config.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| format!("couldn't start it: {e}"))?;
+
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let (tx, answers) = channel();
+        // Stops when the plugin closes its stdout (the channel is disconnected) or when we're gone.
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            let answer = match stdout.read_line(&mut line) {
+                Ok(0) => return,
+                Ok(_) => Ok(line),
+                Err(e) => Err(e),
+            };
+            if tx.send(answer).is_err() {
+                return;
+            }
+        });
+
+        Ok(Self {
+            stdin: child.stdin.take(),
+            answers,
+            timeout,
+            child,
+        })
+    }
+
+    /// Sends a message (a line of JSON) and reads the answer, killing the plugin if it takes too long.
+    fn call(&mut self, message: &str) -> Result<String, String> {
+        let stdin = self.stdin.as_mut().ok_or("stdin is closed")?;
+        writeln!(stdin, "{message}")
+            .and_then(|()| stdin.flush())
+            .map_err(|e| format!("couldn't send a message: {e}"))?;
+
+        match self.answers.recv_timeout(self.timeout) {
+            Ok(Ok(line)) => Ok(line),
+            Ok(Err(e)) => Err(format!("couldn't read its answer: {e}")),
+            Err(RecvTimeoutError::Disconnected) => Err("exited without answering".to_string()),
+            Err(RecvTimeoutError::Timeout) => {
+                self.stdin = None;
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                Err(format!(
+                    "didn't answer within {:?} (see `timeout`), it was stopped",
+                    self.timeout
+                ))
+            }
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // Closing stdin tells the plugin we're done, give it a second to exit by itself.
+        drop(self.stdin.take());
+        for _ in 0..100 {
+            if !matches!(self.child.try_wait(), Ok(None)) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// WebAssembly plugins, see the [module's documentation](self)
+#[cfg(feature = "wasm")]
+mod wasm {
+    use std::path::Path;
+
+    use wasmi::{
+        Config, Engine, Linker, Memory, Store, StoreLimits, StoreLimitsBuilder, TrapCode, TypedFunc,
+    };
+
+    /// Instructions (roughly) a module can run for each message, so a plugin stuck in a loop fails instead of hanging the build.
+    pub const FUEL: u64 = 1_000_000_000;
+    /// Maximum size of a module's memory (256 MiB)
+    const MEMORY_LIMIT: usize = 256 << 20;
+
+    pub struct Module {
+        store: Store<StoreLimits>,
+        /// Fuel for each message
+        fuel: u64,
+        memory: Memory,
+        alloc: TypedFunc<i32, i32>,
+        call: TypedFunc<(i32, i32), i64>,
+    }
+
+    impl Module {
+        pub fn load(path: &Path, fuel: u64) -> Result<Self, String> {
+            let wasm = std::fs::read(path).map_err(|e| format!("couldn't read it: {e}"))?;
+            let mut config = Config::default();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config);
+            let module = wasmi::Module::new(&engine, wasm)
+                .map_err(|e| format!("invalid WebAssembly module: {e}"))?;
+
+            let limits = StoreLimitsBuilder::new()
+                .memory_size(MEMORY_LIMIT)
+                // Trapping says what happened, a failed `memory.grow` would make the module fail in its own way.
+                .trap_on_grow_failure(true)
+                .build();
+            let mut store = Store::new(&engine, limits);
+            store.limiter(|limits| limits);
+            store
+                .set_fuel(fuel)
+                .map_err(|e| format!("couldn't set its fuel: {e}"))?;
+            // Nothing is linked, so the module can only compute: no filesystem, network, clock...
+            let instance = Linker::new(&engine)
+                .instantiate_and_start(&mut store, &module)
+                .map_err(|e| {
+                    format!("couldn't instantiate it (plugins can't import anything): {e}")
+                })?;
+
+            let memory = instance
+                .get_memory(&store, "memory")
+                .ok_or("doesn't export `memory`")?;
+            let alloc = instance
+                .get_typed_func(&store, "cuteness_alloc")
+                .map_err(|e| format!("`cuteness_alloc`: {e}"))?;
+            let call = instance
+                .get_typed_func(&store, "cuteness_call")
+                .map_err(|e| format!("`cuteness_call`: {e}"))?;
+
+            Ok(Self {
+                store,
+                fuel,
+                memory,
+                alloc,
+                call,
+            })
+        }
+
+        /// Copies a message to the module's memory, handles it and reads the answer back.
+        pub fn call(&mut self, message: &str) -> Result<String, String> {
+            let len = i32::try_from(message.len()).map_err(|_| "message too long")?;
+            self.store
+                .set_fuel(self.fuel)
+                .map_err(|e| format!("couldn't set its fuel: {e}"))?;
+            let ptr = self
+                .alloc
+                .call(&mut self.store, len)
+                .map_err(|e| self.failed("cuteness_alloc", e))?;
+            self.memory
+                .write(&mut self.store, ptr as u32 as usize, message.as_bytes())
+                .map_err(|e| format!("couldn't write a message: {e}"))?;
+
+            let answer = self
+                .call
+                .call(&mut self.store, (ptr, len))
+                .map_err(|e| self.failed("cuteness_call", e))? as u64;
+
+            // Checked before allocating anything, the length comes from the module.
+            let (ptr, len) = ((answer >> 32) as usize, (answer & 0xffff_ffff) as usize);
+            let size = self.memory.data_size(&self.store);
+            if ptr.checked_add(len).is_none_or(|end| end > size) {
+                return Err(format!(
+                    "its answer ({len} bytes at {ptr}) is outside of its memory"
+                ));
+            }
+            let mut buffer = vec![0; len];
+            self.memory
+                .read(&self.store, ptr, &mut buffer)
+                .map_err(|e| format!("couldn't read its answer: {e}"))?;
+
+            String::from_utf8(buffer).map_err(|e| format!("invalid answer: {e}"))
+        }
+
+        /// Explains why calling one of the module's functions failed, limits included.
+        fn failed(&self, function: &str, error: wasmi::Error) -> String {
+            match error.as_trap_code() {
+                Some(TrapCode::OutOfFuel) => format!(
+                    "`{function}` took too long (it ran out of fuel, {} instructions), is it stuck in a loop?",
+                    self.fuel
+                ),
+                Some(TrapCode::GrowthOperationLimited) => format!(
+                    "`{function}` needed more than {} MiB of memory",
+                    MEMORY_LIMIT >> 20
+                ),
+                _ => format!("`{function}` failed: {error}"),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Loads a module whose `cuteness_call` has `body` (the message is at 1024), `wasmi` parses modules written as text too. Its memory starts with `{"protocol":1}` (14 bytes).
+        fn module(name: &str, body: &str, fuel: u64) -> Result<Module, String> {
+            let path = std::env::temp_dir().join(format!(
+                "cuteness-plugin-{name}-{}.wasm",
+                std::process::id()
+            ));
+            std::fs::write(
+                &path,
+                format!(
+                    r#"(module
+                        (memory (export "memory") 1)
+                        (data (i32.const 0) "{{\"protocol\":1}}")
+                        (func (export "cuteness_alloc") (param i32) (result i32) (i32.const 1024))
+                        (func (export "cuteness_call") (param i32 i32) (result i64) {body}))"#
+                ),
+            )
+            .unwrap();
+            let module = Module::load(&path, fuel);
+            std::fs::remove_file(path).unwrap();
+            module
+        }
+
+        fn call(name: &str, body: &str) -> Result<String, String> {
+            module(name, body, FUEL)?.call("{}")
+        }
+
+        #[test]
+        fn answers() {
+            assert_eq!(
+                call("answers", "(i64.const 14)").as_deref(),
+                Ok(r#"{"protocol":1}"#)
+            );
+        }
+
+        #[test]
+        fn answers_outside_of_the_memory() {
+            for (name, answer) in [
+                ("too-long", "0xffffffff"),
+                ("too-far", "0x0001_0000_0000_0001"),
+                ("overflow", "0xffff_ffff_ffff_ffff"),
+            ] {
+                let error = call(name, &format!("(i64.const {answer})")).unwrap_err();
+                assert!(error.contains("outside of its memory"), "{error}");
+            }
+        }
+
+        #[test]
+        fn loops_run_out_of_fuel() {
+            let error = module("loop", "(loop $again (br $again)) (i64.const 0)", 100_000)
+                .unwrap()
+                .call("{}")
+                .unwrap_err();
+            assert!(error.contains("ran out of fuel"), "{error}");
+        }
+
+        #[test]
+        fn memory_is_limited() {
+            let error = call(
+                "memory",
+                "(drop (memory.grow (i32.const 10000))) (i64.const 14)",
+            )
+            .unwrap_err();
+            assert!(error.contains("more than 256 MiB of memory"), "{error}");
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Starts a shell script as a plugin.
+    fn start(script: &str, timeout: Option<f64>) -> Result<Plugin> {
+        Plugin::start(
+            &std::env::temp_dir(),
+            &PluginConfig {
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), script.to_string()],
+                timeout,
+            },
+        )
+    }
+
+    fn page() -> PageConfig {
+        toml::from_str("title = \"A\"").unwrap()
+    }
+
+    fn failure(result: Result<impl Sized>) -> String {
+        match result {
+            Err(Error::Plugin { message, .. }) => message,
+            Err(e) => panic!("not a plugin error: {e}"),
+            Ok(_) => panic!("the plugin didn't fail"),
+        }
+    }
+
+    #[test]
+    fn processes_answer() {
+        let plugin = start(
+            r#"read _; echo '{"protocol":1}'; while read _; do echo '{"markdown":"Hi"}'; done"#,
+            None,
+        )
+        .unwrap();
+        let response = plugin
+            .run(Path::new("src/a.md"), &page(), "Bye".into())
+            .unwrap();
+        assert_eq!(response.markdown.as_deref(), Some("Hi"));
+    }
+
+    #[test]
+    fn processes_time_out() {
+        let started = std::time::Instant::now();
+        let message = failure(start("sleep 10", Some(0.2)));
+        assert!(
+            message.starts_with("didn't answer within 200ms"),
+            "{message}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Slow for a page, not for the handshake
+        let plugin = start(
+            r#"read _; echo '{"protocol":1}'; read _; sleep 10"#,
+            Some(0.2),
+        )
+        .unwrap();
+        let message = failure(plugin.run(Path::new("src/a.md"), &page(), String::new()));
+        assert!(message.contains("didn't answer"), "{message}");
+    }
+
+    #[test]
+    fn processes_fail() {
+        assert_eq!(failure(start("exit 0", None)), "exited without answering");
+        assert!(failure(start("exit 0", Some(-1.0))).starts_with("invalid timeout `-1`"));
+    }
+}
@@ -0,0 +1,109 @@
+//! Preprocessors transform a page after parsing it and before rendering it to HTML, see [`Preprocessor`].
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Arc;
+
+use handlebars::Handlebars;
+use pulldown_cmark::Event;
+
+use crate::admonitions::Admonish;
+use crate::config::{Config, PageConfig};
+use crate::typography::{Emojis, Typography};
+use crate::{Error, Result};
+
+/// A transform applied to every page (unless it's disabled in `[preprocessors]` or in the page's front matter).
+///
+/// Register your own with [`SiteBuilder::preprocessor`](crate::SiteBuilder::preprocessor).
+pub trait Preprocessor: Send + Sync {
+    /// Name used to enable, disable or order it in `cuteconfig.toml` and in the front matter.
+    fn name(&self) -> &str;
+
+    /// Transforms a page's events, its front matter can be changed too (e.g. to add CSS files).
+    fn run<'a>(
+        &self,
+        page: &PageContext<'_>,
+        front_matter: &mut PageConfig,
+        events: Vec<Event<'a>>,
+    ) -> Result<Vec<Event<'a>>>;
+}
+
+/// What a [`Preprocessor`] knows about the page it's transforming.
+pub struct PageContext<'r> {
+    /// Source file, relative to the project's root
+    pub path: &'r Path,
+    pub config: &'r Config,
+    /// Registered templates, e.g. `admonish`
+    pub registry: &'r Handlebars<'static>,
+    pub(crate) warnings: RefCell<Vec<String>>,
+}
+
+impl PageContext<'_> {
+    /// Reports a problem that doesn't stop the build, it's shown with the page's path (see [`Site::warnings`](crate::Site::warnings)).
+    pub fn warn(&self, message: impl Into<String>) {
+        self.warnings.borrow_mut().push(message.into());
+    }
+}
+
+/// The built-in preprocessors, in the order they run by default.
+pub fn builtin() -> Vec<Arc<dyn Preprocessor>> {
+    vec![Arc::new(Admonish), Arc::new(Typography), Arc::new(Emojis)]
+}
+
+/// Picks the preprocessors for a page: in the order set in `[preprocessors]` (unlisted ones run after, in their default order), without the disabled ones.
+///
+/// A page can disable more preprocessors or enable some of the ones disabled in `cuteconfig.toml`.
+pub fn select<'p>(
+    available: &'p [Arc<dyn Preprocessor>],
+    config: &Config,
+    page: &PageConfig,
+    path: &Path,
+) -> Result<Vec<&'p dyn Preprocessor>> {
+    let empty = Vec::new();
+    let (order, project_disable) = config
+        .preprocessors
+        .as_ref()
+        .map_or((&empty, &empty), |p| (&p.order, &p.disable));
+    let (enable, page_disable) = page
+        .preprocessors
+        .as_ref()
+        .map_or((&empty, &empty), |p| (&p.enable, &p.disable));
+
+    for (file, names) in [
+        (Path::new("cuteconfig.toml"), order),
+        (Path::new("cuteconfig.toml"), project_disable),
+        (path, enable),
+        (path, page_disable),
+    ] {
+        if let Some(name) = names
+            .iter()
+            .find(|name| !available.iter().any(|p| p.name() == name.as_str()))
+        {
+            return Err(Error::UnknownPreprocessor {
+                name: name.clone(),
+                path: file.to_path_buf(),
+            });
+        }
+    }
+
+    let is_in = |list: &[String], name: &str| list.iter().any(|n| n == name);
+
+    let mut selected: Vec<&dyn Preprocessor> = available
+        .iter()
+        .map(Arc::as_ref)
+        .filter(|p| {
+            let disabled = is_in(project_disable, p.name()) || is_in(page_disable, p.name());
+            !disabled || is_in(enable, p.name())
+        })
+        .collect();
+
+    // Stable sort: listed ones first, by position in `order`
+    selected.sort_by_key(|p| {
+        order
+            .iter()
+            .position(|name| name == p.name())
+            .unwrap_or(order.len())
+    });
+
+    Ok(selected)
+}
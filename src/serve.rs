@@ -0,0 +1,257 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use cuteness::links::percent_decode;
+use cuteness::Site;
+
+use crate::watch::{report, watch};
+
+/// Endpoint used by the pages to listen for reloads (Server-Sent Events).
+const RELOAD_ENDPOINT: &str = "/__cuteness/livereload";
+
+/// Snippet injected in every served HTML page, it reloads the page when the server says so.
+const RELOAD_SCRIPT: &str = r#"<script>new EventSource("/__cuteness/livereload").onmessage = () => location.reload();</script>"#;
+
+/// Senders for every open page, a message means "reload".
+type Clients = Arc<Mutex<Vec<Sender<()>>>>;
+
+/// Builds the project, serves `<outdir>/static` and rebuilds (see [`watch`]) + reloads the open pages every time a source file changes.
+pub fn serve(port: u16, outdir: &Path, sassbin: String) -> Result<()> {
+    let mut site = Site::builder(".")
+        .port(port)
+        .outdir(outdir)
+        .sassbin(sassbin)
+        .load()?;
+    site.build()?;
+    report(&site);
+
+    let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+
+    // * Start the HTTP server ===================
+
+    let server = Server::http(("127.0.0.1", port))
+        .map_err(|e| anyhow!("Couldn't start server at port {port}: {e}"))?;
+    let root = site.outdir().join("static");
+
+    {
+        let clients = Arc::clone(&clients);
+        thread::spawn(move || {
+            for request in server.incoming_requests() {
+                let root = root.clone();
+                let clients = Arc::clone(&clients);
+                thread::spawn(move || {
+                    if let Err(e) = handle(request, &root, &clients) {
+                        eprintln!("Couldn't answer request: {e}");
+                    }
+                });
+            }
+        });
+    }
+
+    println!("Serving at http://localhost:{port}/ (Ctrl+C to stop)");
+
+    // * Watch sources and rebuild ===============
+
+    watch(site, || {
+        println!("Reloading pages");
+        clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(()).is_ok());
+    })
+}
+
+fn handle(request: Request, root: &Path, clients: &Clients) -> Result<()> {
+    let url = request.url().split(['?', '#']).next().unwrap_or_default();
+
+    if url == RELOAD_ENDPOINT {
+        return live_reload(request, clients);
+    }
+
+    let Some(path) = resolve(root, url) else {
+        let body = fs::read(root.join("404.html")).unwrap_or_default();
+        request.respond(
+            Response::from_data(body)
+                .with_status_code(StatusCode(404))
+                .with_header(content_type("html")),
+        )?;
+        return Ok(());
+    };
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut body =
+        fs::read(&path).with_context(|| format!("Couldn't read file `{}`", path.display()))?;
+
+    if extension == "html" {
+        body = inject_reload_script(&String::from_utf8_lossy(&body)).into_bytes();
+    }
+
+    request.respond(Response::from_data(body).with_header(content_type(&extension)))?;
+    Ok(())
+}
+
+/// Maps an URL to a file in `root`, pages are served without their `.html` extension (like the Rocket server does).
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let url = percent_decode(url)?;
+    let relative = Path::new(url.trim_start_matches('/'));
+
+    // Don't serve anything outside of `root`.
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let path = root.join(relative);
+    [
+        path.clone(),
+        path.with_extension("html"),
+        path.join("index.html"),
+    ]
+    .into_iter()
+    .find(|candidate| candidate.is_file())
+}
+
+fn inject_reload_script(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{RELOAD_SCRIPT}{}", &html[..index], &html[index..]),
+        None => format!("{html}{RELOAD_SCRIPT}"),
+    }
+}
+
+/// Keeps the connection open as a Server-Sent Events stream, sending a message on every rebuild.
+fn live_reload(request: Request, clients: &Clients) -> Result<()> {
+    let (tx, rx) = channel();
+    clients.lock().unwrap().push(tx);
+
+    let mut writer = request.into_writer();
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+    )?;
+    writer.flush()?;
+
+    loop {
+        let message: &[u8] = match rx.recv_timeout(Duration::from_secs(15)) {
+            Ok(()) => b"data: reload\n\n",
+            // Pings let us notice closed pages.
+            Err(RecvTimeoutError::Timeout) => b": ping\n\n",
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+
+        if writer
+            .write_all(message)
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn content_type(extension: &str) -> Header {
+    let mime = match extension {
+        "html" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "txt" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    };
+
+    Header::from_bytes(&b"Content-Type"[..], mime.as_bytes()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_paths() {
+        let dir = std::env::temp_dir().join(format!("cuteness-serve-{}", std::process::id()));
+        let root = dir.join("static");
+        let _ = fs::remove_dir_all(&dir);
+        for file in [
+            "secret.html",
+            "static/index.html",
+            "static/a.html",
+            "static/my page.html",
+            "static/guide/index.html",
+            "static/styles/main.css",
+        ] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+
+        assert_eq!(resolve(&root, "/"), Some(root.join("index.html")));
+        assert_eq!(resolve(&root, "/a"), Some(root.join("a.html")));
+        assert_eq!(resolve(&root, "/a.html"), Some(root.join("a.html")));
+        assert_eq!(
+            resolve(&root, "/my%20page"),
+            Some(root.join("my page.html"))
+        );
+        assert_eq!(
+            resolve(&root, "/guide"),
+            Some(root.join("guide/index.html"))
+        );
+        assert_eq!(
+            resolve(&root, "/guide/"),
+            Some(root.join("guide/index.html"))
+        );
+        assert_eq!(
+            resolve(&root, "/styles/main.css"),
+            Some(root.join("styles/main.css"))
+        );
+
+        // Not found
+        assert_eq!(resolve(&root, "/missing"), None);
+        assert_eq!(resolve(&root, "/styles"), None);
+
+        // Nothing outside of the root
+        for url in [
+            "/../secret",
+            "/../secret.html",
+            "/%2e%2e/secret",
+            "/%2E%2E/secret",
+            "/guide/..%2f..%2fsecret",
+            "/./a",
+            "/%ff",
+        ] {
+            assert_eq!(resolve(&root, url), None, "{url}");
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_script() {
+        assert_eq!(
+            inject_reload_script("<html><body><p>Hi</p></body></html>"),
+            format!("<html><body><p>Hi</p>{RELOAD_SCRIPT}</body></html>")
+        );
+        assert_eq!(
+            inject_reload_script("<p>Hi</p>"),
+            format!("<p>Hi</p>{RELOAD_SCRIPT}")
+        );
+    }
+}
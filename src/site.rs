@@ -0,0 +1,1755 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{self, read_to_string, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use handlebars::{
+    handlebars_helper, no_escape, Context as HandlebarsContext, Handlebars, Helper, HelperResult,
+    Output, RenderContext, RenderError,
+};
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Serialize;
+use serde_json::json;
+use walkdir::WalkDir;
+use yaml_front_matter::{Document, YamlFrontMatter};
+
+use crate::cache::{dependencies_hash, file_hash, Cache};
+use crate::config::{Config, PageConfig, SummaryConfig};
+use crate::diagrams::Diagrams;
+use crate::error::Context;
+use crate::highlight::{self, Highlighter};
+use crate::links::{check_links, collect_anchors, find_links, BrokenLink, PageLinks};
+use crate::plugins::Plugin;
+use crate::preprocessors::PageContext;
+use crate::templates::Templates;
+use crate::toc::TocEntry;
+use crate::{admonitions, directives, include, math, toc};
+use crate::{compile_styles, params_in_path, preprocessors, Error, Method, Preprocessor, Result};
+
+/// Options for loading and building a project, see [`Site::builder`].
+///
+/// ```no_run
+/// # fn main() -> cuteness::Result<()> {
+/// let output = cuteness::Site::builder("path/to/project")
+///     .outdir("public")
+///     .in_memory(true)
+///     .build()?;
+///
+/// for page in &output.pages {
+///     println!("{} -> /{}", page.path, page.route);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SiteBuilder {
+    root: PathBuf,
+    outdir: PathBuf,
+    port: u16,
+    sassbin: String,
+    no_cache: bool,
+    jobs: usize,
+    in_memory: bool,
+    preprocessors: Vec<Arc<dyn Preprocessor>>,
+}
+
+impl SiteBuilder {
+    /// Options for the project at `root` (the directory with `cuteconfig.toml`), with the same defaults as `cuteness build`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            outdir: PathBuf::from("www"),
+            port: 8080,
+            sassbin: String::from("sass"),
+            no_cache: false,
+            jobs: 0,
+            in_memory: false,
+            preprocessors: preprocessors::builtin(),
+        }
+    }
+
+    /// Output directory, relative to the project's root (unless it's absolute). Defaults to `www`.
+    pub fn outdir(mut self, outdir: impl Into<PathBuf>) -> Self {
+        self.outdir = outdir.into();
+        self
+    }
+
+    /// Port the generated server listens on. Defaults to `8080`.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Command for the Sass compiler. Defaults to `sass`.
+    pub fn sassbin(mut self, sassbin: impl Into<String>) -> Self {
+        self.sassbin = sassbin.into();
+        self
+    }
+
+    /// Ignore the build cache from previous builds (a new one is still written).
+    pub fn no_cache(mut self, no_cache: bool) -> Self {
+        self.no_cache = no_cache;
+        self
+    }
+
+    /// Number of threads used to render the pages, `0` (the default) means one per CPU.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Don't write anything to the output directory, the rendered HTML is returned in [`Page::html`] instead. The build cache isn't used.
+    pub fn in_memory(mut self, in_memory: bool) -> Self {
+        self.in_memory = in_memory;
+        self
+    }
+
+    /// Adds a preprocessor, it runs after the built-in ones (unless `[preprocessors]` says otherwise).
+    pub fn preprocessor(mut self, preprocessor: impl Preprocessor + 'static) -> Self {
+        self.preprocessors.push(Arc::new(preprocessor));
+        self
+    }
+
+    /// Registers the templates and reads `cuteconfig.toml` and `SUMMARY.toml`, without rendering anything.
+    pub fn load(self) -> Result<Site> {
+        let root = &self.root;
+
+        let config = read_config(root)?;
+
+        let templates = Templates::new(root, config.misc.theme.as_deref().map(Path::new))?;
+        let reg = registry(&templates)?;
+
+        let outdir = root.join(&self.outdir);
+        let mut cache = if self.no_cache || self.in_memory {
+            Cache::default()
+        } else {
+            Cache::load(&outdir)
+        };
+        cache.set_dependencies(dependencies_hash(
+            root,
+            &templates,
+            &self.preprocessors,
+            &config.plugins,
+        )?);
+
+        let pool = ThreadPoolBuilder::new().num_threads(self.jobs).build()?;
+
+        let plugins = config
+            .plugins
+            .iter()
+            .map(|plugin| Plugin::start(root, plugin))
+            .collect::<Result<_>>()?;
+
+        let highlighter = config
+            .misc
+            .syntax_highlighting
+            .unwrap_or(false)
+            .then(Highlighter::new);
+        let diagrams = (!config.diagrams.is_empty()).then(|| {
+            Diagrams::new(
+                root,
+                config.diagrams.clone(),
+                (!self.in_memory).then_some(outdir.as_path()),
+            )
+        });
+
+        Ok(Site {
+            outdir,
+            reg,
+            templates,
+            config,
+            summary: read_summary(root)?,
+            pages: BTreeMap::new(),
+            links: BTreeMap::new(),
+            warnings: BTreeMap::new(),
+            includes: BTreeMap::new(),
+            cache,
+            pool,
+            plugins,
+            highlighter,
+            diagrams,
+            options: self,
+        })
+    }
+
+    /// Loads and builds the project, see [`Site::build`].
+    pub fn build(self) -> Result<BuildOutput> {
+        self.load()?.build()
+    }
+}
+
+/// A loaded project: templates, configuration, summary and the pages rendered so far.
+///
+/// Keeping this around lets `watch` and `serve` re-render only the parts affected by a change.
+pub struct Site {
+    options: SiteBuilder,
+    /// Output directory (already joined to the root)
+    outdir: PathBuf,
+    reg: Handlebars<'static>,
+    templates: Templates,
+    config: Config,
+    summary: SummaryConfig,
+    /// Rendered pages, by source path relative to the root (sorted, so the generated routes are deterministic)
+    pages: BTreeMap<PathBuf, Page>,
+    /// Links and anchors of every rendered page, by source path
+    links: BTreeMap<PathBuf, PageLinks>,
+    /// Warnings of every rendered page, by source path
+    warnings: BTreeMap<PathBuf, Vec<String>>,
+    /// Files included (`{{#include}}`) by every rendered page, by source path
+    includes: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    cache: Cache,
+    /// Thread pool used to render the pages
+    pool: ThreadPool,
+    /// Running `[[plugin]]`s, in the order they're listed
+    plugins: Vec<Plugin>,
+    /// Only loaded if `syntax_highlighting` is enabled
+    highlighter: Option<Highlighter>,
+    /// Only loaded if there are `[diagrams]`
+    diagrams: Option<Diagrams>,
+}
+
+/// A rendered page
+#[derive(Serialize, Clone)]
+pub struct Page {
+    pub config: PageConfig,
+    /// Source file, relative to the project's root (e.g. `src/introduction.md`)
+    pub path: String,
+    /// Where the page is served, relative to the site's root (e.g. `guide/introduction`)
+    pub route: String,
+    /// Name of the page's handler in the generated server
+    pub handler: String,
+    /// Rendered HTML, only kept in [in-memory builds](SiteBuilder::in_memory)
+    #[serde(skip)]
+    pub html: Option<String>,
+}
+
+/// A route of the generated Rocket server
+#[derive(Serialize, Clone)]
+pub struct Route {
+    pub method: Method,
+    /// Rocket path, with the page's parameters as dynamic segments (e.g. `/users/<id>`)
+    pub path: String,
+    /// Source file of the page served at this route, relative to the project's root
+    pub source: PathBuf,
+}
+
+/// What [`Site::build`] generated
+#[derive(Clone)]
+pub struct BuildOutput {
+    pub pages: Vec<Page>,
+    pub routes: Vec<Route>,
+}
+
+/// A problem found while rendering a page, see [`Site::warnings`]
+pub struct Warning {
+    /// Source file, relative to the project's root
+    pub file: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.file.display(), self.message)
+    }
+}
+
+impl Site {
+    /// Options for the project at `root`, see [`SiteBuilder`].
+    pub fn builder(root: impl Into<PathBuf>) -> SiteBuilder {
+        SiteBuilder::new(root)
+    }
+
+    /// Re-registers the templates and re-reads every configuration file, keeping the options.
+    pub fn reload(&mut self) -> Result<()> {
+        let cache = std::mem::take(&mut self.cache);
+        *self = self.options.clone().no_cache(true).load()?;
+        // `no_cache` was only needed to skip reading the cache we already have.
+        self.options.no_cache = false;
+        self.cache = cache;
+        self.cache.set_dependencies(dependencies_hash(
+            &self.options.root,
+            &self.templates,
+            &self.options.preprocessors,
+            &self.config.plugins,
+        )?);
+        Ok(())
+    }
+
+    /// Builds the whole project, returning every rendered page and generated route.
+    pub fn build(&mut self) -> Result<BuildOutput> {
+        self.create_dirs()?;
+        self.render_all()?;
+        self.write_routing()?;
+        self.copy_404()?;
+        self.compile_styles()?;
+        self.save_cache()?;
+        Ok(self.output())
+    }
+
+    /// Every page rendered so far and the routes generated for them.
+    pub fn output(&self) -> BuildOutput {
+        BuildOutput {
+            pages: self.pages.values().cloned().collect(),
+            routes: self
+                .pages
+                .iter()
+                .map(|(path, page)| Route {
+                    method: page.config.method.clone(),
+                    path: format!("/{}", page.route),
+                    source: path.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Re-reads `SUMMARY.toml`, every page has to be re-rendered after this (the sidebar changed).
+    pub fn reload_summary(&mut self) -> Result<()> {
+        self.summary = read_summary(&self.options.root)?;
+        self.cache.set_dependencies(dependencies_hash(
+            &self.options.root,
+            &self.templates,
+            &self.options.preprocessors,
+            &self.config.plugins,
+        )?);
+        Ok(())
+    }
+
+    /// The project's root directory
+    pub fn root(&self) -> &Path {
+        &self.options.root
+    }
+
+    /// The output directory (already joined to the root)
+    pub fn outdir(&self) -> &Path {
+        &self.outdir
+    }
+
+    /// Directories searched for templates (see [`Templates`]), changes in them affect every page.
+    pub fn template_dirs(&self) -> &[PathBuf] {
+        self.templates.dirs()
+    }
+
+    /// Writes the build cache, so the next build can skip unchanged pages.
+    pub fn save_cache(&self) -> Result<()> {
+        if self.options.in_memory {
+            return Ok(());
+        }
+        self.cache.save(&self.outdir)
+    }
+
+    fn create_dirs(&self) -> Result<()> {
+        if self.options.in_memory {
+            return Ok(());
+        }
+        let outdir = &self.outdir;
+
+        // * Create output directory ======================
+
+        if !Path::new(outdir).exists() {
+            fs::create_dir(outdir)
+                .with_context(|| format!("Couldn't create directory {}", outdir.display()))?;
+        };
+
+        {
+            let routing_path = Path::new(&outdir).join("routing");
+            if !routing_path.exists() {
+                fs::create_dir(&routing_path).context("Couldn't create directory `routing`")?;
+            };
+            if !routing_path.join("src").exists() {
+                fs::create_dir(routing_path.join("src"))
+                    .context("Couldn't create directory `routing/src`")?;
+            };
+        };
+
+        if !Path::new(&outdir.join("static")).exists() {
+            fs::create_dir(outdir.join("static")).with_context(|| {
+                format!(
+                    "Couldn't create directory `{}`",
+                    outdir.join("static").display()
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Walks `src` and renders every Markdown file in it, in parallel.
+    pub fn render_all(&mut self) -> Result<()> {
+        self.pages.clear();
+        self.links.clear();
+        self.warnings.clear();
+        self.includes.clear();
+
+        // Collect the pages first, then render them in the thread pool. `collect` keeps the order, so the output is deterministic.
+        let paths = source_pages(&self.options.root);
+
+        check_collisions(paths.iter().map(PathBuf::as_path))?;
+
+        let rendered = self.pool.install(|| {
+            paths
+                .par_iter()
+                .map(|path| self.render(path))
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        for page in rendered {
+            self.insert_page(page);
+        }
+
+        Ok(())
+    }
+
+    /// Converts a single Markdown file (`path` is relative to the root) to HTML and writes it to the output directory.
+    pub fn render_page(&mut self, path: &Path) -> Result<()> {
+        let mut paths: BTreeSet<&Path> = self.pages.keys().map(PathBuf::as_path).collect();
+        paths.insert(path);
+        check_collisions(paths.into_iter())?;
+
+        let page = self.render(path)?;
+        self.insert_page(page);
+        Ok(())
+    }
+
+    fn insert_page(&mut self, page: RenderedPage) {
+        if let Some(source) = &page.source {
+            self.cache.insert(
+                &page.path,
+                source,
+                page.config.clone(),
+                page.links.clone(),
+                page.warnings.clone(),
+                page.includes.clone(),
+            );
+        }
+
+        self.links.insert(page.path.clone(), page.links);
+        self.warnings.insert(page.path.clone(), page.warnings);
+        self.includes
+            .insert(page.path.clone(), page.includes.into_keys().collect());
+        self.pages.insert(
+            page.path.clone(),
+            Page {
+                config: page.config,
+                path: page.path.to_string_lossy().to_string(),
+                route: page_route(&page.path),
+                handler: page_handler(&page.path),
+                html: page.html.filter(|_| self.options.in_memory),
+            },
+        );
+    }
+
+    /// Renders a page, only needs shared access to the site so pages can be rendered in parallel.
+    ///
+    /// Pages that didn't change since the last build (see [`Cache`]) are skipped.
+    fn render(&self, path: &Path) -> Result<RenderedPage> {
+        let outdir = &self.outdir;
+        let reg = &self.reg;
+
+        let content = read_to_string(self.options.root.join(path))
+            .context("Can't get path of file in the input directory")?;
+
+        // * Skip unchanged pages ==================
+
+        if let Some(cached) = self
+            .cache
+            .get(&self.options.root, path, &content)
+            .filter(|_| page_output(outdir, path).exists())
+        {
+            return Ok(RenderedPage {
+                path: path.to_path_buf(),
+                config: cached.config.clone(),
+                links: cached.links.clone(),
+                warnings: cached.warnings.clone(),
+                includes: cached.includes.clone(),
+                source: None,
+                html: None,
+            });
+        }
+
+        // * Read and check the source ============
+
+        let mut source = read_page(
+            &self.options.root,
+            path,
+            &content,
+            &self.config,
+            &self.options.preprocessors,
+        )?;
+        let includes = std::mem::take(&mut source.includes)
+            .into_iter()
+            .map(|file| {
+                let hash = file_hash(&self.options.root.join(&file)).unwrap_or_default();
+                (file, hash)
+            })
+            .collect();
+
+        // * Run plugins ===========================
+
+        let mut plugin_html = None;
+        for plugin in &self.plugins {
+            let markdown = std::mem::take(&mut source.markdown);
+            let response = plugin.run(path, &source.config, markdown)?;
+            if let Some(html) = response.html {
+                plugin_html = Some(html);
+                break;
+            }
+            source.markdown = response.markdown.unwrap_or_default();
+        }
+
+        // =======================================
+
+        let context = PageContext {
+            path,
+            config: &self.config,
+            registry: reg,
+            warnings: RefCell::new(std::mem::take(&mut source.warnings)),
+        };
+
+        // Directives' lines are replaced by comments (without changing line numbers), their templates are applied to the HTML.
+        let (binding, page_directives) = match plugin_html {
+            Some(_) => (String::new(), Vec::new()),
+            None => directives::mark(&source.markdown, &mut |m| context.warn(m)),
+        };
+        // Equations are replaced by tokens, so Markdown doesn't change them.
+        let (binding, equations) = match self.config.misc.latex.unwrap_or(false) {
+            true => math::mark(&binding),
+            false => (binding, Vec::new()),
+        };
+        // Links in the Markdown with their offset, they give the links found after rendering their line.
+        let mut parsed_links = Vec::new();
+
+        let mut events: Vec<Event> = match plugin_html {
+            Some(html) => vec![Event::Html(html.into())],
+            None => Parser::new_ext(&binding, markdown_options())
+                .into_offset_iter()
+                .inspect(|(event, range)| {
+                    if let Event::Start(Tag::Link(_, url, _) | Tag::Image(_, url, _)) = event {
+                        parsed_links.push((url.to_string(), range.start));
+                    }
+                })
+                .map(|(event, _)| event)
+                .collect(),
+        };
+        events = templates_as_html(events);
+
+        // * Preprocess ============================
+
+        for preprocessor in preprocessors::select(
+            &self.options.preprocessors,
+            &self.config,
+            &source.config,
+            path,
+        )? {
+            events = preprocessor.run(&context, &mut source.config, events)?;
+        }
+
+        events = math::render(
+            events,
+            &equations,
+            self.config
+                .misc
+                .latex_macros
+                .as_ref()
+                .unwrap_or(&BTreeMap::new()),
+            &mut |m| context.warn(m),
+        );
+        // Before highlighting, diagrams' languages may be known by the highlighter too.
+        if let Some(diagrams) = &self.diagrams {
+            events = diagrams.render(events, !self.options.no_cache, &mut |m| context.warn(m));
+        }
+        if let Some(highlighter) = &self.highlighter {
+            events = highlighter.highlight(events);
+        }
+        let (events, toc) = toc::headings(events);
+        let mut links = PageLinks {
+            links: find_links(
+                &events,
+                &binding,
+                source.front_matter_lines + 1,
+                &parsed_links,
+            ),
+            ..PageLinks::default()
+        };
+        // What templates get as `page`: the front matter and the table of contents
+        let mut page = json!(&source.config);
+        page["toc"] = json!(toc);
+
+        let mut html_output = String::new();
+        html::push_html(&mut html_output, events.into_iter());
+        html_output = directives::render(&html_output, &page_directives, reg, &page, &mut |m| {
+            context.warn(m)
+        })?;
+
+        // =======================================
+
+        // * Render in-markdown templates (the user can use handlebars even from the files)
+
+        html_output = reg
+            .render_template(&html_output, &json!({"page": &page, "outer": &self.config}))
+            .map_err(|e| Error::template(path.to_string_lossy(), e))?;
+        html_output = include::restore(&html_output);
+
+        // =======================================
+
+        // * Render using page's configuration ===
+
+        let page_html = reg
+            .render(
+                "page_template",
+                &json!({
+                "content": html_output,
+                "sidebar": self.summary,
+                // Pages are nested like their sources, links to the root (styles, sidebar...) have to go up to it.
+                "root": "../".repeat(page_route(path).matches('/').count()),
+                "page": &page,
+                "toc": &toc,
+                    "misc": &self.config.misc,
+                    "admonitions": !self.config.admonitions.is_empty()
+                }),
+            )
+            .map_err(|e| Error::template("page.html.hbs", e))?;
+        links.anchors = collect_anchors(&page_html);
+
+        if !self.options.in_memory {
+            let output_path = page_output(outdir, path);
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Couldn't create directory `{}`", parent.display()))?;
+            }
+            let mut f = File::create(&output_path).with_context(|| {
+                format!("Couldn't create / open file `{}`", output_path.display())
+            })?;
+            f.write_if_different(page_html.as_bytes(), &output_path)?;
+        }
+        // =======================================
+
+        Ok(RenderedPage {
+            path: path.to_path_buf(),
+            config: source.config,
+            links,
+            warnings: context.warnings.into_inner(),
+            includes,
+            source: Some(content),
+            html: Some(page_html),
+        })
+    }
+
+    /// Finds links (in the pages and the sidebar) that don't point to any page, asset or anchor.
+    pub fn check_links(&self) -> Vec<BrokenLink> {
+        check_links(&self.options.root, &self.outdir, &self.links, &self.summary)
+    }
+
+    /// Files included (`{{#include}}`) by the rendered pages, relative to the root.
+    pub fn included_files(&self) -> BTreeSet<&Path> {
+        self.includes
+            .values()
+            .flatten()
+            .map(PathBuf::as_path)
+            .collect()
+    }
+
+    /// Pages including `file` (relative to the root), they have to be re-rendered when it changes.
+    pub fn pages_including(&self, file: &Path) -> Vec<PathBuf> {
+        self.includes
+            .iter()
+            .filter(|(_, included)| included.contains(file))
+            .map(|(page, _)| page.clone())
+            .collect()
+    }
+
+    /// Problems found while rendering the pages (e.g. unknown admonition kinds), they don't stop the build.
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.warnings
+            .iter()
+            .flat_map(|(path, warnings)| {
+                warnings.iter().map(|message| Warning {
+                    file: path.clone(),
+                    message: message.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Forgets a page whose source was deleted and removes its output.
+    pub fn remove_page(&mut self, path: &Path) -> Result<()> {
+        self.pages.remove(path);
+        self.links.remove(path);
+        self.warnings.remove(path);
+        self.includes.remove(path);
+        self.cache.remove(path);
+        if self.options.in_memory {
+            return Ok(());
+        }
+
+        let output_path = page_output(&self.outdir, path);
+        if output_path.exists() {
+            fs::remove_file(&output_path)
+                .with_context(|| format!("Couldn't remove file `{}`", output_path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Generates the Rocket server (`Cargo.toml`, `src/main.rs` and `Rocket.toml`) for the pages rendered so far.
+    pub fn write_routing(&self) -> Result<()> {
+        if self.options.in_memory {
+            return Ok(());
+        }
+        let outdir = &self.outdir;
+        let reg = &self.reg;
+
+        // * Create Cargo project
+
+        let binding = outdir.join("routing");
+        let cargo_project = Path::new(&binding);
+
+        {
+            let mut f = File::create(cargo_project.join("Cargo.toml")).with_context(|| {
+                format!(
+                    "Couldn't create | open file {}/Cargo.toml",
+                    outdir.display()
+                )
+            })?;
+
+            f.write_all(
+                reg.render("rocket_cargo_toml", &json!({}))
+                    .map_err(|e| Error::template("routing/Cargo.toml.hbs", e))?
+                    .as_bytes(),
+            )
+            .context("Couldn't write to routing file")?;
+        }
+
+        // Rocket renders the dynamic pages with `page.html.hbs`, from the `templates` directory next to the server.
+        if !cargo_project.join("templates").exists() {
+            fs::create_dir(cargo_project.join("templates"))
+                .context("Couldn't create directory `routing/templates`")?;
+        }
+        fs::write(
+            cargo_project.join("templates").join("page.html.hbs"),
+            self.templates.get("page.html.hbs")?.as_bytes(),
+        )
+        .context("Couldn't write `routing/templates/page.html.hbs`")?;
+
+        let mut f = File::create(cargo_project.join("src").join("main.rs")).with_context(|| {
+            format!(
+                "Couldn't create | open file {}/src/main.rs",
+                outdir.display()
+            )
+        })?;
+
+        let pages: Vec<&Page> = self.pages.values().collect();
+
+        f.write_if_different(
+            reg.render(
+                "rocket_routing_template",
+                &json!({
+                    "port": self.options.port,
+                    "directory": std::fs::canonicalize(outdir).context("Couldn't canonicalize output directory")?.join("static"),
+                    "pages": pages,
+                    "config_path": std::fs::canonicalize(cargo_project).context("Couldn't canonicalize routing directory")?
+                }),
+            ).map_err(|e| Error::template("routing/src/main.rs.hbs", e))?
+                .as_bytes(),
+            cargo_project.join("src").join("main.rs"))?;
+
+        let mut f = File::create(cargo_project.join("Rocket.toml")).with_context(|| {
+            format!(
+                "Couldn't create | open file {}",
+                cargo_project.join("Rocket.toml").display()
+            )
+        })?;
+
+        f.write_if_different(
+            reg.render(
+                "rocket_toml",
+                &json!({
+                    "config_path": std::fs::canonicalize(cargo_project).context("Couldn't canonicalize routing directory")?
+                }),
+            )
+            .map_err(|e| Error::template("routing/Rocket.toml.hbs", e))?
+            .as_bytes(),
+            cargo_project.join("Rocket.toml"),
+        )?;
+
+        Ok(())
+    }
+
+    fn copy_404(&self) -> Result<()> {
+        if self.options.in_memory {
+            return Ok(());
+        }
+        fs::write(
+            self.outdir.join("static").join("404.html"),
+            self.templates.get("404.html")?.as_bytes(),
+        )
+        .context("Couldn't copy 404 page (templates/404.html)")?;
+        Ok(())
+    }
+
+    /// Compiles the user's styles (`src/styles`) and copies the built-in ones.
+    pub fn compile_styles(&self) -> Result<()> {
+        if self.options.in_memory {
+            return Ok(());
+        }
+        let outdir = &self.outdir;
+        let styles = self.options.root.join("src").join("styles");
+
+        // * Compile styles ==========================
+
+        if styles.exists() {
+            compile_styles(
+                &styles.to_string_lossy(),
+                &format!("{}/static/styles", &outdir.display()),
+                #[cfg(feature = "sass")]
+                &self.options.sassbin,
+            )?;
+        }
+
+        if !Path::new(&format!("{}/static/styles", outdir.display())).exists() {
+            fs::create_dir(format!("{}/static/styles", outdir.display())).with_context(|| {
+                format!(
+                    "Couldn't create directory `{}/static/styles`",
+                    outdir.display()
+                )
+            })?;
+        }
+
+        // * Generate styles ==========================
+
+        if !self.config.admonitions.is_empty() {
+            let path = outdir
+                .join("static")
+                .join("styles")
+                .join("admonition-kinds.css");
+            fs::write(
+                &path,
+                admonitions::stylesheet(&self.options.root, &self.config.admonitions)?,
+            )
+            .with_context(|| format!("Couldn't write `{}`", path.display()))?;
+        }
+
+        if self.highlighter.is_some() {
+            let path = outdir.join("static").join("styles").join("highlight.css");
+            let theme = self
+                .config
+                .misc
+                .highlight_theme
+                .as_deref()
+                .unwrap_or(highlight::DEFAULT_THEME);
+            fs::write(&path, highlight::stylesheet(&self.options.root, theme)?)
+                .with_context(|| format!("Couldn't write `{}`", path.display()))?;
+        }
+
+        // * Copy built-in styles ====================
+
+        // Project and theme styles override the built-in ones (see `Templates`)
+
+        for (name, content) in self.templates.styles()? {
+            fs::write(outdir.join("static").join("styles").join(&name), content).with_context(
+                || {
+                    format!(
+                        "Couldn't copy style `{name}` to `{}/static/styles/{name}`",
+                        outdir.display(),
+                    )
+                },
+            )?;
+        }
+
+        // ===========================================
+
+        Ok(())
+    }
+}
+
+/// A page rendered by [`Site::render`], waiting to be added to the site.
+struct RenderedPage {
+    path: PathBuf,
+    config: PageConfig,
+    links: PageLinks,
+    warnings: Vec<String>,
+    /// Hashes of the files it includes, by path relative to the root
+    includes: BTreeMap<PathBuf, String>,
+    /// Source the page was rendered from, `None` if it was skipped because of the cache.
+    source: Option<String>,
+    /// `None` if it was skipped because of the cache.
+    html: Option<String>,
+}
+
+/// Registers all templates and helpers
+fn registry(templates: &Templates) -> Result<Handlebars<'static>> {
+    let mut reg = handlebars::Handlebars::new();
+    reg.register_escape_fn(no_escape);
+    for (name, file) in [
+        ("page_template", "page.html.hbs"),
+        ("rocket_routing_template", "routing/src/main.rs.hbs"),
+        ("rocket_toml", "routing/Rocket.toml.hbs"),
+        ("rocket_cargo_toml", "routing/Cargo.toml.hbs"),
+        ("admonish", "admonish.html.hbs"),
+    ] {
+        reg.register_template_string(name, templates.get(file)?)
+            .map_err(|e| Error::template(file, e))?;
+    }
+    for (name, template) in templates.directives()? {
+        let file = format!("directives/{name}.hbs");
+        reg.register_template_string(&format!("directives/{name}"), template)
+            .map_err(|e| Error::template(file, e))?;
+    }
+
+    // `{{toc}}`: the page's table of contents (or the one given, `{{toc entries}}`) as nested lists
+    reg.register_helper(
+        "toc",
+        Box::new(
+            |h: &Helper,
+             _: &Handlebars,
+             ctx: &HandlebarsContext,
+             _: &mut RenderContext,
+             out: &mut dyn Output|
+             -> HelperResult {
+                let entries = match h.param(0) {
+                    Some(param) => param.value().clone(),
+                    None => ctx.data()["page"]["toc"].clone(),
+                };
+                let entries: Vec<TocEntry> = serde_json::from_value(entries).unwrap_or_default();
+                out.write(&toc::html(&entries))?;
+                Ok(())
+            },
+        ),
+    );
+
+    handlebars_helper!(lower: |method: String| method.to_lowercase());
+    reg.register_helper("lower", Box::new(lower));
+
+    reg.register_helper("file_name", Box::new(file_name));
+
+    handlebars_helper!(sanitize: |path: String| {
+
+        lazy_static!{
+            static ref RE: regex::Regex = regex::Regex::new("([<>])").unwrap();
+        }RE.replace_all(&path, "_").to_string()
+    });
+    reg.register_helper("sanitize", Box::new(sanitize));
+
+    handlebars_helper!(contains: |src: String, search: String| { src.contains(&search)});
+    reg.register_helper("contains", Box::new(contains));
+
+    handlebars_helper!(is_pure: |src: String| {
+        lazy_static!{
+            static ref RE: regex::Regex = regex::Regex::new("([<>])").unwrap();
+        };
+
+        !RE.is_match(&src)
+    });
+    reg.register_helper("is_pure", Box::new(is_pure));
+
+    reg.register_helper("cut_end", Box::new(cut_end));
+    reg.register_helper("cut_start", Box::new(cut_start));
+
+    Ok(reg)
+}
+
+/// `{{file_name path}}`: the file name of `path` without its (three characters long, e.g. `.md`) extension
+fn file_name(
+    h: &Helper,
+    _: &Handlebars,
+    _: &HandlebarsContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let path = string_param(h, 0)?;
+    let name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| RenderError::new(format!("`file_name`: `{path}` has no file name")))?;
+    let name = cut(name, 0, 3)
+        .ok_or_else(|| RenderError::new(format!("`file_name`: `{name}` has no extension")))?;
+    out.write(name)?;
+    Ok(())
+}
+
+/// `{{cut_end src n}}`: `src` without its last `n` characters
+fn cut_end(
+    h: &Helper,
+    _: &Handlebars,
+    _: &HandlebarsContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let (src, n) = (string_param(h, 0)?, count_param(h, 1)?);
+    out.write(cut(src, 0, n).ok_or_else(|| too_short("cut_end", src, n))?)?;
+    Ok(())
+}
+
+/// `{{cut_start src n}}`: `src` without its first `n` characters
+fn cut_start(
+    h: &Helper,
+    _: &Handlebars,
+    _: &HandlebarsContext,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let (src, n) = (string_param(h, 0)?, count_param(h, 1)?);
+    out.write(cut(src, n, 0).ok_or_else(|| too_short("cut_start", src, n))?)?;
+    Ok(())
+}
+
+/// `src` without its first `start` and last `end` characters, `None` if it's shorter than that.
+fn cut(src: &str, start: usize, end: usize) -> Option<&str> {
+    let len = src.chars().count();
+    if start + end > len {
+        return None;
+    }
+    let offset = |n: usize| src.char_indices().nth(n).map_or(src.len(), |(i, _)| i);
+    Some(&src[offset(start)..offset(len - end)])
+}
+
+fn string_param<'a>(h: &'a Helper, index: usize) -> Result<&'a str, RenderError> {
+    h.param(index)
+        .and_then(|param| param.value().as_str())
+        .ok_or_else(|| {
+            RenderError::new(format!(
+                "`{}`: parameter {index} should be a string",
+                h.name()
+            ))
+        })
+}
+
+fn count_param(h: &Helper, index: usize) -> Result<usize, RenderError> {
+    h.param(index)
+        .and_then(|param| param.value().as_u64())
+        .map(|n| n as usize)
+        .ok_or_else(|| {
+            RenderError::new(format!(
+                "`{}`: parameter {index} should be a positive integer",
+                h.name()
+            ))
+        })
+}
+
+fn too_short(helper: &str, src: &str, n: usize) -> RenderError {
+    RenderError::new(format!(
+        "`{helper}`: `{src}` is shorter than {n} characters"
+    ))
+}
+
+/// Generate sidebar from SUMMARY.toml
+/// Markdown files in `<root>/src` (the pages), relative to the root and sorted.
+pub fn source_pages(root: &Path) -> Vec<PathBuf> {
+    WalkDir::new(root.join("src"))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".md"))
+        .map(|e| {
+            e.path()
+                .strip_prefix(root)
+                .unwrap_or(e.path())
+                .to_path_buf()
+        })
+        .collect()
+}
+
+/// A page's source, see [`read_page`]
+pub struct PageSource {
+    pub config: PageConfig,
+    /// Markdown after the front matter, with the `{{#include}}`s expanded
+    pub markdown: String,
+    /// Lines in the Markdown map to lines in the source file after these (unless a plugin or an include changed them).
+    pub front_matter_lines: usize,
+    /// Files included by the page, relative to the root
+    pub includes: BTreeSet<PathBuf>,
+    /// Problems that don't stop the build (e.g. an included file outside of the project)
+    pub warnings: Vec<String>,
+}
+
+/// Reads a page (`path` is relative to `root`, `content` is the file's) and checks everything builds check before rendering it: its front matter and parameters, its `{{#include}}`s and the `preprocessors` it enables or disables.
+pub fn read_page(
+    root: &Path,
+    path: &Path,
+    content: &str,
+    config: &Config,
+    preprocessors: &[Arc<dyn Preprocessor>],
+) -> Result<PageSource> {
+    let document: Document<PageConfig> =
+        YamlFrontMatter::parse::<PageConfig>(content).map_err(|e| Error::FrontMatter {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+    let front_matter_lines = content
+        .lines()
+        .count()
+        .saturating_sub(document.content.split('\n').count());
+
+    // Every parameter has to be in the page's path
+    let params_in_page = params_in_path(path);
+    for param in document.metadata.params.iter().flatten() {
+        if !params_in_page.contains(&param.name) {
+            return Err(Error::FrontMatter {
+                path: path.to_path_buf(),
+                message: format!("unknown parameter `{}` (not in the path)", param.name),
+            });
+        }
+    }
+
+    preprocessors::select(preprocessors, config, &document.metadata, path)?;
+
+    // Reported with the page's other warnings, once there's a context for them.
+    let mut warnings = Vec::new();
+    let (markdown, includes) =
+        include::expand(root, path, &document.content, &mut |m| warnings.push(m))?;
+
+    Ok(PageSource {
+        config: document.metadata,
+        markdown,
+        front_matter_lines,
+        includes,
+        warnings,
+    })
+}
+
+/// Reads `cuteconfig.toml`, in `root`.
+pub fn read_config(root: &Path) -> Result<Config> {
+    let mut content = String::new();
+    let config_path = root.join("cuteconfig.toml");
+    if !config_path.exists() {
+        return Err(Error::NotFound(config_path));
+    }
+
+    let mut f = File::open(&config_path).context("Couldn't open `cuteconfig.toml`")?;
+    f.read_to_string(&mut content)
+        .context("Couldn't read configuration `cuteconfig.toml`")?;
+
+    toml::from_str(&content).map_err(|source| Error::Config {
+        path: config_path,
+        source,
+    })
+}
+
+/// Reads `SUMMARY.toml`, in `root`.
+pub fn read_summary(root: &Path) -> Result<SummaryConfig> {
+    let path = root.join("SUMMARY.toml");
+    if !path.exists() {
+        return Err(Error::NotFound(path));
+    }
+
+    toml::from_str(&read_to_string(&path).context("Couldn't get file `SUMMARY.toml`")?)
+        .map_err(|source| Error::Summary { path, source })
+}
+
+/// Markdown extensions used to parse pages.
+///
+/// Quotes, dashes... are handled by the `typography` preprocessor, which knows about Handlebars expressions.
+pub(crate) fn markdown_options() -> Options {
+    Options::all() - Options::ENABLE_SMART_PUNCTUATION
+}
+
+/// Turns the Handlebars expressions (`{{...}}`) in the text of `events` into raw HTML, so they aren't escaped (`{{ page "title" }}` would become `{{ page &quot;title&quot; }}`) nor changed by preprocessors. Code is left alone.
+///
+/// Adjacent text events are merged first, the parser may split an expression. An expression that isn't closed runs until the end of its block.
+pub(crate) fn templates_as_html(events: Vec<Event>) -> Vec<Event> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut in_code_block = false;
+    let mut in_template = false;
+    let mut events = events.into_iter().peekable();
+
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(first) if !in_code_block => {
+                let mut text = first.into_string();
+                while let Some(Event::Text(next)) = events.peek() {
+                    text.push_str(next);
+                    events.next();
+                }
+                split_templates(&text, &mut in_template, &mut result);
+                continue;
+            }
+            // Inline formatting doesn't end an expression, blocks do.
+            Event::End(
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..),
+            ) => {}
+            Event::End(_) => in_template = false,
+            _ => {}
+        }
+
+        result.push(event);
+    }
+
+    result
+}
+
+/// Splits `text` in text and Handlebars expressions (as raw HTML), `in_template` is whether it starts inside of one.
+fn split_templates<'a>(text: &str, in_template: &mut bool, events: &mut Vec<Event<'a>>) {
+    let mut rest = text;
+    while !rest.is_empty() {
+        let delimiter = if *in_template {
+            // `{{{raw}}}` ends with its three braces
+            rest.find("}}").map(|end| {
+                let after = &rest[end + 2..];
+                end + 2 + after.len() - after.trim_start_matches('}').len()
+            })
+        } else {
+            rest.find("{{")
+        };
+        let end = delimiter.unwrap_or(rest.len());
+
+        if end > 0 {
+            let part = CowStr::Boxed(rest[..end].into());
+            events.push(match *in_template {
+                true => Event::Html(part),
+                false => Event::Text(part),
+            });
+        }
+        if delimiter.is_some() {
+            *in_template = !*in_template;
+        }
+        rest = &rest[end..];
+    }
+}
+
+/// Route (URL, relative to the root) of the page at `path`: `src/<dir>/<name>.md` is served at `/<dir>/<name>`
+pub fn page_route(path: &Path) -> String {
+    path.strip_prefix("src")
+        .unwrap_or(path)
+        .with_extension("")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Name of the handler for the page at `path` in the generated server: its route, with anything that can't be in an identifier replaced by `_`
+pub fn page_handler(path: &Path) -> String {
+    let handler: String = page_route(path)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if handler.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{handler}")
+    } else {
+        handler
+    }
+}
+
+/// Fails if two pages would be written to the same file (e.g. `Intro.md` and `intro.md` on a case-insensitive file system) or would have the same handler in the generated server.
+pub fn check_collisions<'a>(paths: impl Iterator<Item = &'a Path>) -> Result<()> {
+    let mut outputs: BTreeMap<String, &Path> = BTreeMap::new();
+    let mut handlers: BTreeMap<String, &Path> = BTreeMap::new();
+
+    for path in paths {
+        if let Some(first) = outputs.insert(page_route(path).to_lowercase(), path) {
+            return Err(Error::Collision {
+                first: first.to_path_buf(),
+                second: path.to_path_buf(),
+                what: "output path",
+            });
+        }
+        if let Some(first) = handlers.insert(page_handler(path), path) {
+            return Err(Error::Collision {
+                first: first.to_path_buf(),
+                second: path.to_path_buf(),
+                what: "handler name",
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// `src/<dir>/<name>.md` is written to `<outdir>/static/<dir>/<name>.html`
+fn page_output(outdir: &Path, path: &Path) -> PathBuf {
+    outdir
+        .join("static")
+        .join(format!("{}.html", page_route(path)))
+}
+
+/// Write to file ONLY if the contents are different
+pub(crate) trait WriteIfDifferent {
+    /// Writes
+    fn write_if_different<P: AsRef<Path>>(&mut self, buf: &[u8], path: P) -> Result<()>;
+}
+
+impl<W> WriteIfDifferent for W
+where
+    W: Write,
+{
+    fn write_if_different<P: AsRef<Path>>(&mut self, buf: &[u8], path: P) -> Result<()> {
+        // Check hashes
+
+        if !(path.as_ref().exists()
+            && blake3::hash(buf)
+                == blake3::hash(
+                    read_to_string(&path)
+                        .with_context(|| format!("Couldn't read `{}`", path.as_ref().display()))?
+                        .as_bytes(),
+                ))
+        {
+            self.write_all(buf)
+                .with_context(|| format!("Couldn't write to `{}`", path.as_ref().display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a project in a new temporary directory: a minimal `cuteconfig.toml` and `SUMMARY.toml`, and `files` (path relative to the root, contents).
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cuteness-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let defaults = [
+            (
+                "cuteconfig.toml",
+                "[misc]\nhtml_lang = \"en\"\n\n[config]\n",
+            ),
+            ("SUMMARY.toml", "map = []\n"),
+        ];
+        for (path, content) in defaults.iter().chain(files) {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    /// Builds the project in memory, returns the HTML of every page by source path.
+    fn build(root: &Path) -> BTreeMap<String, String> {
+        Site::builder(root)
+            .in_memory(true)
+            .build()
+            .unwrap()
+            .pages
+            .into_iter()
+            .map(|page| (page.path, page.html.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn links_have_their_line() {
+        let root = project(
+            "link-lines",
+            &[(
+                "src/index.md",
+                "---\ntitle: Index\n---\nFirst line\n[Broken](missing) at the start of line 5\n\nAnd [again](missing#x).",
+            )],
+        );
+        let mut site = Site::builder(&root).in_memory(true).load().unwrap();
+        site.render_all().unwrap();
+
+        let lines: Vec<_> = site.check_links().iter().map(|link| link.line).collect();
+        assert_eq!(lines, [Some(5), Some(7)]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn links_are_found_after_rendering() {
+        /// Adds a link to every page, like a plugin or a preprocessor writing HTML may
+        struct Footer;
+
+        impl Preprocessor for Footer {
+            fn name(&self) -> &str {
+                "footer"
+            }
+
+            fn run<'a>(
+                &self,
+                _page: &PageContext<'_>,
+                _front_matter: &mut PageConfig,
+                mut events: Vec<Event<'a>>,
+            ) -> Result<Vec<Event<'a>>> {
+                events.push(Event::Html(r#"<a href="missing-footer">Footer</a>"#.into()));
+                Ok(events)
+            }
+        }
+
+        let root = project(
+            "found-links",
+            &[
+                (
+                    "src/index.md",
+                    "---\ntitle: Index\n---\n[Encoded](my%20page#s%C3%A9)\n\n<a href=\"missing-raw\">Raw</a>\n",
+                ),
+                ("src/my page.md", "---\ntitle: My page\n---\n# Sé\n"),
+            ],
+        );
+        let mut site = Site::builder(&root)
+            .in_memory(true)
+            .preprocessor(Footer)
+            .load()
+            .unwrap();
+        site.render_all().unwrap();
+
+        let broken: Vec<_> = site
+            .check_links()
+            .iter()
+            .map(|link| (link.file.clone(), link.url.clone(), link.line))
+            .collect();
+        assert_eq!(
+            broken,
+            [
+                (
+                    PathBuf::from("src/index.md"),
+                    "missing-raw".to_string(),
+                    Some(6)
+                ),
+                (
+                    PathBuf::from("src/index.md"),
+                    "missing-footer".to_string(),
+                    None
+                ),
+                (
+                    PathBuf::from("src/my page.md"),
+                    "missing-footer".to_string(),
+                    None
+                )
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn templates_are_html() {
+        let events =
+            Parser::new_ext("ab {{ \"ab\" }} ab {{{c}}}d {{ab", markdown_options()).collect();
+        assert_eq!(
+            templates_as_html(events),
+            vec![
+                Event::Start(Tag::Paragraph),
+                Event::Text("ab ".into()),
+                Event::Html("{{ \"ab\" }}".into()),
+                Event::Text(" ab ".into()),
+                Event::Html("{{{c}}}".into()),
+                Event::Text("d ".into()),
+                // Not closed, until the end of the block
+                Event::Html("{{ab".into()),
+                Event::End(Tag::Paragraph),
+            ]
+        );
+
+        // Closed in another text event
+        let events = vec![Event::Text("{{ a".into()), Event::Text("b }}ab".into())];
+        assert_eq!(
+            templates_as_html(events),
+            vec![Event::Html("{{ ab }}".into()), Event::Text("ab".into())]
+        );
+
+        // Code isn't a template
+        let events = Parser::new_ext("`{{a}}`\n\n```\n{{b}}\n```", markdown_options()).collect();
+        assert!(templates_as_html(events)
+            .iter()
+            .all(|event| !matches!(event, Event::Html(_))));
+    }
+
+    #[test]
+    fn templates_arent_escaped_without_preprocessors() {
+        let root = project(
+            "templates-without-preprocessors",
+            &[
+                (
+                    "cuteconfig.toml",
+                    "[misc]\nhtml_lang = \"en\"\n\n[config]\n\n[preprocessors]\ndisable = [\"admonish\", \"typography\", \"emojis\"]\n",
+                ),
+                (
+                    "src/index.md",
+                    "---\ntitle: Index\n---\n{{#if (eq page.title \"Index\")}}Home \"page\"{{/if}}\n",
+                ),
+            ],
+        );
+        let html = &build(&root)["src/index.md"];
+
+        assert!(html.contains("<p>Home &quot;page&quot;</p>"), "{html}");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn builds_are_deterministic() {
+        let pages: Vec<(String, String)> = (0..24)
+            .map(|i| {
+                (
+                    format!("src/{}/page{i}.md", ["a", "b", "c"][i % 3]),
+                    format!(
+                        "---\ntitle: Page {i}\n---\n# Page {i}\n\n[Next](page{})\n\n```admonish kind{}\nBody\n```\n",
+                        i + 1,
+                        i % 2
+                    ),
+                )
+            })
+            .collect();
+        let files: Vec<_> = pages
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .collect();
+        let root = project("deterministic", &files);
+
+        let build = |jobs| {
+            let mut site = Site::builder(&root)
+                .in_memory(true)
+                .jobs(jobs)
+                .load()
+                .unwrap();
+            let output = site.build().unwrap();
+            (
+                output
+                    .pages
+                    .into_iter()
+                    .map(|page| (page.path, page.route, page.html))
+                    .collect::<Vec<_>>(),
+                output
+                    .routes
+                    .into_iter()
+                    .map(|route| route.path)
+                    .collect::<Vec<_>>(),
+                site.warnings()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+                site.check_links()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        let sequential = build(1);
+        assert_eq!(sequential.0.len(), 24);
+        assert!(!sequential.2.is_empty() && !sequential.3.is_empty());
+        for _ in 0..3 {
+            assert_eq!(build(4), sequential);
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn admonitions_have_headings_and_links() {
+        let root = project(
+            "admonitions",
+            &[(
+                "src/index.md",
+                "---\ntitle: Index\n---\n# Index\n\n```admonish info\n## Inside\n\n[Broken](missing)\n```\n",
+            )],
+        );
+        let mut site = Site::builder(&root).in_memory(true).load().unwrap();
+        site.render_all().unwrap();
+        let html = site.output().pages[0].html.clone().unwrap();
+
+        assert!(html.contains(
+            r##"<h2 id="inside">Inside<a class="heading-anchor" href="#inside"></a></h2>"##
+        ));
+        assert!(html.contains(r##"<a href="#inside">Inside</a>"##));
+        let broken: Vec<_> = site
+            .check_links()
+            .iter()
+            .map(|link| (link.url.clone(), link.line))
+            .collect();
+        assert_eq!(broken, [("missing".to_string(), Some(9))]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn unchanged_pages_are_cached() {
+        let root = project(
+            "cache",
+            &[
+                ("src/a.md", "---\ntitle: A\n---\nA"),
+                ("src/b.md", "---\ntitle: B\n---\nB"),
+            ],
+        );
+        let load = |no_cache| Site::builder(&root).no_cache(no_cache).load().unwrap();
+        // Whether the page is rendered, or skipped because of the cache
+        let rendered =
+            |site: &Site, path: &str| site.render(Path::new(path)).unwrap().source.is_some();
+
+        let mut site = load(false);
+        site.create_dirs().unwrap();
+        site.render_all().unwrap();
+        site.save_cache().unwrap();
+
+        let site = load(false);
+        assert!(!rendered(&site, "src/a.md"));
+        assert!(!rendered(&site, "src/b.md"));
+
+        fs::write(root.join("src/a.md"), "---\ntitle: A\n---\nChanged").unwrap();
+        assert!(rendered(&site, "src/a.md"));
+        assert!(!rendered(&site, "src/b.md"));
+
+        // Deleted output
+        fs::remove_file(page_output(site.outdir(), Path::new("src/b.md"))).unwrap();
+        assert!(rendered(&site, "src/b.md"));
+
+        // `--no-cache`
+        assert!(rendered(&load(true), "src/b.md"));
+
+        // What every page depends on
+        fs::write(
+            root.join("cuteconfig.toml"),
+            "[misc]\nhtml_lang = \"fr\"\n\n[config]\n",
+        )
+        .unwrap();
+        assert!(rendered(&load(false), "src/b.md"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn project_templates_override_the_theme() {
+        let root = project(
+            "overrides",
+            &[
+                (
+                    "cuteconfig.toml",
+                    "[misc]\nhtml_lang = \"en\"\ntheme = \"theme\"\n\n[config]\n",
+                ),
+                ("theme/page.html.hbs", "theme: {{content}}"),
+                (
+                    "theme/admonish.html.hbs",
+                    "<aside class=\"{{kind}}\">{{{content}}}</aside>",
+                ),
+                ("templates/page.html.hbs", "{{page.title}}: {{content}}"),
+                (
+                    "src/index.md",
+                    "---\ntitle: Index\n---\n```admonish tip\nHi\n```\n",
+                ),
+            ],
+        );
+        assert_eq!(
+            build(&root)["src/index.md"],
+            "Index: <aside class=\"tip\">\n<p>Hi</p>\n</aside>"
+        );
+
+        fs::remove_file(root.join("templates/page.html.hbs")).unwrap();
+        assert!(build(&root)["src/index.md"].starts_with("theme: <aside"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn nested_pages_link_to_the_root() {
+        let root = project(
+            "nested",
+            &[(
+                "src/guide/intro.md",
+                "---\ntitle: Intro\nadditional_css: [styles/x.css, https://example.com/y.css]\n---\nHi",
+            )],
+        );
+        let html = &build(&root)["src/guide/intro.md"];
+
+        assert!(html.contains(r#"href="../styles/index.css""#));
+        assert!(html.contains(r#"href="../styles/x.css""#));
+        assert!(html.contains(r#"href="https://example.com/y.css""#));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn directives_render_their_markdown() {
+        let root = project(
+            "directives",
+            &[
+                (
+                    "templates/directives/card.hbs",
+                    "<div class=\"card {{attrs.class}}\">{{content}}</div>",
+                ),
+                (
+                    "src/index.md",
+                    "---\ntitle: Index\n---\n::: card class=wide\n## Title\n\n**Bold** text\n:::\n",
+                ),
+            ],
+        );
+        let html = &build(&root)["src/index.md"];
+
+        assert!(html.contains(
+            r##"<div class="card wide"><h2 id="title">Title<a class="heading-anchor" href="#title"></a></h2>
+<p><strong>Bold</strong> text</p></div>"##
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn pages_have_a_toc() {
+        // Both ways the documentation shows
+        let root = project(
+            "toc",
+            &[(
+                "src/index.md",
+                "---\ntitle: Index\n---\n<nav class=\"page-toc\">{{toc}}</nav>\n\n<ul class=\"chapters\">\n{{#each page.toc}}\n<li><a href=\"#{{this.id}}\">{{{this.title}}}</a></li>\n{{/each}}\n</ul>\n\n# Intro & more\n## Usage\n# Intro & more\n",
+            )],
+        );
+        let html = &build(&root)["src/index.md"];
+
+        let toc = r##"<ul><li><a href="#intro--more">Intro &amp; more</a><ul><li><a href="#usage">Usage</a></li></ul></li><li><a href="#intro--more-1">Intro &amp; more</a></li></ul>"##;
+        assert!(
+            html.contains(&format!(r#"<nav class="page-toc">{toc}</nav>"#)),
+            "{html}"
+        );
+        assert!(html.contains(
+            r##"<ul class="chapters">
+<li><a href="#intro--more">Intro &amp; more</a></li>
+<li><a href="#intro--more-1">Intro &amp; more</a></li>
+</ul>"##
+        ));
+        assert!(html.contains(
+            r##"<h2 id="usage">Usage<a class="heading-anchor" href="#usage"></a></h2>"##
+        ));
+        // The "On this page" panel
+        assert!(html.contains(&format!(
+            "<nav class=\"cutetoc\">\n\t\t\t<p>On this page</p>\n\t\t\t{toc}"
+        )));
+
+        let styles = Templates::new(&root, None).unwrap().styles().unwrap();
+        let css = String::from_utf8_lossy(&styles["index.css"]);
+        assert!(css.contains(".cutetoc") && css.contains("a.heading-anchor"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn included_code_isnt_a_template() {
+        let root = project(
+            "include-braces",
+            &[
+                (
+                    "src/index.md",
+                    "---\ntitle: Index\n---\n```rust include=main.rs\n```\n\n`{{#include main.rs}}` is {{page.title}}\n",
+                ),
+                ("src/main.rs", "println!(\"{{}}\", 1);\n"),
+            ],
+        );
+        let html = &build(&root)["src/index.md"];
+
+        assert!(html.contains("println!(&quot;{{}}&quot;, 1);"), "{html}");
+        assert!(html.contains("<code>{{#include main.rs}}</code> is Index"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn string_helpers_fail_instead_of_panicking() {
+        let mut reg = Handlebars::new();
+        reg.register_helper("file_name", Box::new(file_name));
+        reg.register_helper("cut_end", Box::new(cut_end));
+        reg.register_helper("cut_start", Box::new(cut_start));
+        let render = |template: &str| reg.render_template(template, &json!({}));
+
+        assert_eq!(render("{{file_name \"src/dir/été.md\"}}").unwrap(), "été");
+        assert_eq!(render("{{cut_end \"héhé\" 2}}").unwrap(), "hé");
+        assert_eq!(render("{{cut_start \"héhé\" 1}}").unwrap(), "éhé");
+        assert_eq!(render("{{cut_start \"hé\" 2}}").unwrap(), "");
+
+        for template in [
+            "{{file_name \"/\"}}",
+            "{{file_name \"src/..\"}}",
+            "{{file_name \"a\"}}",
+            "{{file_name 3}}",
+            "{{cut_end \"hé\" 3}}",
+            "{{cut_start \"hé\" 3}}",
+            "{{cut_end \"hé\" -1}}",
+            "{{cut_start \"hé\"}}",
+        ] {
+            assert!(render(template).is_err(), "{template}");
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Built-in templates and styles, embedded in the binary so cuteness works without running `cuteness setup` (e.g. on machines without internet access).
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::{self, read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::error::Context;
+use crate::{config_path, Error, Result};
+
+/// Every built-in template, by path relative to the `templates` directory.
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("page.html.hbs", include_str!("../templates/page.html.hbs")),
+    (
+        "admonish.html.hbs",
+        include_str!("../templates/admonish.html.hbs"),
+    ),
+    (
+        "routing/src/main.rs.hbs",
+        include_str!("../templates/routing/src/main.rs.hbs"),
+    ),
+    (
+        "routing/Cargo.toml.hbs",
+        include_str!("../templates/routing/Cargo.toml.hbs"),
+    ),
+    (
+        "routing/Rocket.toml.hbs",
+        include_str!("../templates/routing/Rocket.toml.hbs"),
+    ),
+    ("404.html", include_str!("../templates/404.html")),
+    (
+        "styles/index.css",
+        include_str!("../templates/styles/index.css"),
+    ),
+    (
+        "styles/index.css.map",
+        include_str!("../templates/styles/index.css.map"),
+    ),
+];
+
+/// Lookup chain for templates and styles.
+///
+/// A template is searched in, by order:
+///
+/// 1. The project's `templates` directory.
+/// 2. The theme directory (`theme` in `cuteconfig.toml`'s `[misc]`), if any.
+/// 3. The templates installed by `cuteness setup` / `cuteness update` (`<config_path>/templates`).
+/// 4. The built-in templates ([`TEMPLATES`]).
+pub struct Templates {
+    /// Directories to search, by priority
+    dirs: Vec<PathBuf>,
+}
+
+impl Templates {
+    /// Creates the lookup chain for the project at `root`.
+    pub fn new(root: &Path, theme: Option<&Path>) -> Result<Self> {
+        let mut dirs = vec![root.join("templates")];
+        if let Some(theme) = theme {
+            dirs.push(root.join(theme));
+        }
+        dirs.push(config_path()?.join("templates"));
+
+        Ok(Self { dirs })
+    }
+
+    /// Directories searched before falling back to the built-in templates, by priority.
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Gets a template by its path relative to the `templates` directory.
+    pub fn get(&self, name: &str) -> Result<Cow<'static, str>> {
+        if let Some(path) = self.find(name) {
+            return read_to_string(&path)
+                .map(Cow::Owned)
+                .with_context(|| format!("Couldn't read template `{}`", path.display()));
+        }
+
+        TEMPLATES
+            .iter()
+            .find(|(template, _)| *template == name)
+            .map(|(_, content)| Cow::Borrowed(*content))
+            .ok_or_else(|| Error::NotFound(PathBuf::from("templates").join(name)))
+    }
+
+    /// Finds the first file called `name` in the chain's directories.
+    pub fn find(&self, name: &str) -> Option<PathBuf> {
+        self.dirs
+            .iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Gets every style (`styles/*`), by file name. Styles in a directory override the ones with the same name in lower priority directories.
+    pub fn styles(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        let mut styles: BTreeMap<String, Vec<u8>> = TEMPLATES
+            .iter()
+            .filter_map(|(name, content)| {
+                Some((
+                    name.strip_prefix("styles/")?.to_string(),
+                    content.as_bytes().to_vec(),
+                ))
+            })
+            .collect();
+
+        for dir in self.dirs.iter().rev() {
+            let Ok(entries) = read_dir(dir.join("styles")) else {
+                continue;
+            };
+
+            for entry in entries.filter_map(|e| e.ok()) {
+                if !entry.path().is_file() {
+                    continue;
+                }
+
+                styles.insert(
+                    entry.file_name().to_string_lossy().to_string(),
+                    fs::read(entry.path()).with_context(|| {
+                        format!("Couldn't read style `{}`", entry.path().display())
+                    })?,
+                );
+            }
+        }
+
+        Ok(styles)
+    }
+
+    /// Gets every directive template (`directives/<name>.hbs`), by directive name. Like styles, templates in a directory override the ones with the same name in lower priority directories.
+    pub fn directives(&self) -> Result<BTreeMap<String, String>> {
+        let mut directives = BTreeMap::new();
+
+        for dir in self.dirs.iter().rev() {
+            let Ok(entries) = read_dir(dir.join("directives")) else {
+                continue;
+            };
+
+            for entry in entries.filter_map(|e| e.ok()) {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let Some(name) = file_name.strip_suffix(".hbs") else {
+                    continue;
+                };
+                if !entry.path().is_file() {
+                    continue;
+                }
+
+                directives.insert(
+                    name.to_string(),
+                    read_to_string(entry.path()).with_context(|| {
+                        format!("Couldn't read template `{}`", entry.path().display())
+                    })?,
+                );
+            }
+        }
+
+        Ok(directives)
+    }
+
+    /// Hashes every template and style the chain resolves to, see the build cache.
+    pub fn hash(&self) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new();
+
+        for (name, _) in TEMPLATES {
+            hasher.update(name.as_bytes());
+            hasher.update(self.get(name)?.as_bytes());
+        }
+
+        // Templates that aren't built-in (e.g. partials) may be used too.
+        for dir in &self.dirs {
+            for entry in WalkDir::new(dir)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+            {
+                hasher.update(entry.path().to_string_lossy().as_bytes());
+                hasher.update(
+                    &fs::read(entry.path())
+                        .with_context(|| format!("Couldn't read `{}`", entry.path().display()))?,
+                );
+            }
+        }
+
+        Ok(hasher.finalize())
+    }
+}
+
+/// Gets a template using only the global lookup chain (installed templates, then built-in ones).
+pub fn template(name: &str) -> Result<Cow<'static, str>> {
+    Templates {
+        dirs: vec![config_path()?.join("templates")],
+    }
+    .get(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates `files` (path relative to the directory, contents) in a new temporary directory.
+    fn create(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cuteness-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn lookup_chain() {
+        let root = create(
+            "templates",
+            &[
+                ("templates/page.html.hbs", "project"),
+                ("theme/page.html.hbs", "theme"),
+                ("theme/404.html", "theme 404"),
+            ],
+        );
+        let templates = Templates::new(&root, Some(Path::new("theme"))).unwrap();
+        assert_eq!(
+            templates.dirs(),
+            [
+                root.join("templates"),
+                root.join("theme"),
+                config_path().unwrap().join("templates")
+            ]
+        );
+        assert_eq!(templates.get("page.html.hbs").unwrap(), "project");
+        assert_eq!(templates.get("404.html").unwrap(), "theme 404");
+
+        // Without the installed templates, the built-in ones
+        let templates = Templates {
+            dirs: templates.dirs[..2].to_vec(),
+        };
+        assert_eq!(
+            templates.get("admonish.html.hbs").unwrap(),
+            include_str!("../templates/admonish.html.hbs")
+        );
+        assert!(matches!(
+            templates.get("missing.hbs"),
+            Err(Error::NotFound(path)) if path == Path::new("templates/missing.hbs")
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn styles_and_directives_are_merged() {
+        let root = create(
+            "template-styles",
+            &[
+                ("templates/styles/index.css", "project"),
+                ("theme/styles/index.css", "theme"),
+                ("theme/styles/extra.css", "extra"),
+                ("templates/directives/card.hbs", "project card"),
+                ("theme/directives/card.hbs", "theme card"),
+                ("theme/directives/tabs.hbs", "theme tabs"),
+                ("theme/directives/notes.txt", "not a template"),
+            ],
+        );
+        let templates = Templates {
+            dirs: vec![root.join("templates"), root.join("theme")],
+        };
+
+        let styles = templates.styles().unwrap();
+        assert_eq!(styles["index.css"], b"project");
+        assert_eq!(styles["extra.css"], b"extra");
+        assert_eq!(
+            styles["index.css.map"],
+            include_bytes!("../templates/styles/index.css.map")
+        );
+
+        let directives = templates.directives().unwrap();
+        assert_eq!(
+            directives.into_iter().collect::<Vec<_>>(),
+            [
+                ("card".to_string(), "project card".to_string()),
+                ("tabs".to_string(), "theme tabs".to_string()),
+            ]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+}
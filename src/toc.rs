@@ -0,0 +1,241 @@
+//! Heading IDs, anchor links and the page's table of contents.
+//!
+//! Every heading gets an ID (its `{#id}` if it has one, else a slug of its text, made unique in the page) and a link to itself, so sections can be linked to. The headings are returned nested by level, for `{{page.toc}}` and the `{{toc}}` helper (see [`html`]).
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use handlebars::html_escape;
+use pulldown_cmark::{Event, Tag};
+use serde::{Deserialize, Serialize};
+
+/// A heading, and the ones under it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TocEntry {
+    /// `1` to `6`
+    pub level: usize,
+    /// ID of the heading, to link to it (`#{{id}}`)
+    pub id: String,
+    /// Text of the heading, escaped
+    pub title: String,
+    /// Headings with a higher level, until the next heading with this level or a lower one
+    pub children: Vec<TocEntry>,
+}
+
+/// Turns a heading's text into an ID, like GitHub: lowercase, spaces to `-`, punctuation removed.
+pub fn slug(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            '_' => Some('_'),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Gives an ID and an anchor link to every heading in `events`, returns the new events and the table of contents.
+pub fn headings(events: Vec<Event>) -> (Vec<Event>, Vec<TocEntry>) {
+    // Explicit IDs are kept as is, generated ones can't take them.
+    let mut ids: BTreeSet<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Heading(_, Some(id), _)) => Some(id.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut result = Vec::with_capacity(events.len());
+    let mut entries = Vec::new();
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::Heading(level, id, classes)) = event else {
+            result.push(event);
+            continue;
+        };
+
+        let content: Vec<Event> = events
+            .by_ref()
+            .take_while(|event| !matches!(event, Event::End(Tag::Heading(..))))
+            .collect();
+        let text: String = content
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let id = match id {
+            Some(id) => id.to_string(),
+            None => {
+                let base = match slug(&text) {
+                    slug if slug.is_empty() => String::from("section"),
+                    slug => slug,
+                };
+                let mut id = base.clone();
+                for n in 1.. {
+                    if !ids.contains(&id) {
+                        break;
+                    }
+                    id = format!("{base}-{n}");
+                }
+                ids.insert(id.clone());
+                id
+            }
+        };
+        let id = html_escape(&id);
+
+        let class = match classes.is_empty() {
+            true => String::new(),
+            false => format!(r#" class="{}""#, html_escape(&classes.join(" "))),
+        };
+        result.push(Event::Html(format!(r#"<{level} id="{id}"{class}>"#).into()));
+        result.extend(content);
+        result.push(Event::Html(
+            format!(r##"<a class="heading-anchor" href="#{id}"></a></{level}>"##).into(),
+        ));
+        result.push(Event::Html("\n".into()));
+
+        entries.push(TocEntry {
+            level: level as usize,
+            id,
+            title: html_escape(text.trim()),
+            children: Vec::new(),
+        });
+    }
+
+    (result, nest(entries))
+}
+
+/// Renders a table of contents as nested lists of links (`<ul><li><a href="#id">Title</a><ul>...</ul></li></ul>`), what the `{{toc}}` helper shows. Nothing if there are no headings.
+pub fn html(toc: &[TocEntry]) -> String {
+    let mut html = String::new();
+    push_list(&mut html, toc);
+    html
+}
+
+fn push_list(html: &mut String, entries: &[TocEntry]) {
+    if entries.is_empty() {
+        return;
+    }
+
+    html.push_str("<ul>");
+    for entry in entries {
+        // Both are escaped already.
+        let _ = write!(html, r##"<li><a href="#{}">{}</a>"##, entry.id, entry.title);
+        push_list(html, &entry.children);
+        html.push_str("</li>");
+    }
+    html.push_str("</ul>");
+}
+
+/// Nests the headings under the previous one with a lower level.
+fn nest(entries: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut toc = Vec::new();
+    // Headings that can still get children, by level
+    let mut open: Vec<TocEntry> = Vec::new();
+
+    for entry in entries {
+        while open.last().is_some_and(|last| last.level >= entry.level) {
+            close(&mut open, &mut toc);
+        }
+        open.push(entry);
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut toc);
+    }
+
+    toc
+}
+
+/// Closes the last open heading, adding it to its parent.
+fn close(open: &mut Vec<TocEntry>, toc: &mut Vec<TocEntry>) {
+    if let Some(entry) = open.pop() {
+        match open.last_mut() {
+            Some(parent) => parent.children.push(entry),
+            None => toc.push(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{html, Parser};
+
+    use super::*;
+    use crate::site::markdown_options;
+
+    /// Renders `markdown`, returns the HTML and the table of contents.
+    fn render(markdown: &str) -> (String, Vec<TocEntry>) {
+        let (events, toc) = headings(Parser::new_ext(markdown, markdown_options()).collect());
+        let mut html = String::new();
+        html::push_html(&mut html, events.into_iter());
+        (html, toc)
+    }
+
+    /// `(level, id, children)` of every entry
+    fn shape(toc: &[TocEntry]) -> Vec<(usize, &str, usize)> {
+        toc.iter()
+            .map(|entry| (entry.level, entry.id.as_str(), entry.children.len()))
+            .collect()
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Hello, World!"), "hello-world");
+        assert_eq!(slug("  Über_uns 2 "), "über_uns-2");
+        assert_eq!(slug("A - B"), "a---b");
+        assert_eq!(slug("???"), "");
+    }
+
+    #[test]
+    fn anchors() {
+        let (html, _) = render("## The `main` *function* {.api}");
+        assert_eq!(
+            html,
+            "<h2 id=\"the-main-function\" class=\"api\">The <code>main</code> <em>function</em><a class=\"heading-anchor\" href=\"#the-main-function\"></a></h2>\n"
+        );
+    }
+
+    #[test]
+    fn unique_ids() {
+        let (_, toc) = render("# Usage\n# Usage\n# Explicit {#usage-1}\n# Usage\n# !!\n# ?");
+        let ids: Vec<_> = toc.iter().map(|entry| entry.id.as_str()).collect();
+        // Generated IDs don't take explicit ones
+        assert_eq!(
+            ids,
+            [
+                "usage",
+                "usage-2",
+                "usage-1",
+                "usage-3",
+                "section",
+                "section-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_toc() {
+        let (_, toc) = render("## Before\n# A\n### B\n#### C\n## D\n# E <b>&</b>");
+        assert_eq!(shape(&toc), [(2, "before", 0), (1, "a", 2), (1, "e-", 0)]);
+        assert_eq!(shape(&toc[1].children), [(3, "b", 1), (2, "d", 0)]);
+        assert_eq!(shape(&toc[1].children[0].children), [(4, "c", 0)]);
+        // Titles are escaped, raw HTML isn't part of them
+        assert_eq!(toc[2].title, "E &amp;");
+    }
+
+    #[test]
+    fn toc_html() {
+        let (_, toc) = render("# A & B\n## C\n# D");
+        assert_eq!(
+            html(&toc),
+            r##"<ul><li><a href="#a--b">A &amp; B</a><ul><li><a href="#c">C</a></li></ul></li><li><a href="#d">D</a></li></ul>"##
+        );
+        assert_eq!(html(&[]), "");
+    }
+}
@@ -0,0 +1,485 @@
+//! Smart punctuation and emojis, the `typography` and `emojis` [preprocessors](crate::preprocessors). They're applied to the text of a page after parsing it so code, raw HTML and Handlebars expressions (raw HTML too, by then) are left alone.
+//!
+//! * "Straight quotes" become “curly quotes” (or the language's quotation marks, see [`Quotes`]) and apostrophes (`don't`) become `don’t`.
+//! * `--` becomes an en dash (–), `---` an em dash (—) and `...` an ellipsis (…).
+//! * Emojicodes (`:cat:`) become emojis (🐱).
+
+use emojis::get_by_shortcode;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+use crate::config::{MiscConfig, PageConfig};
+use crate::preprocessors::{PageContext, Preprocessor};
+use crate::Result;
+
+/// Quotation marks: `"` is replaced by the primary ones and `'` by the secondary ones (for nested quotes), `[opening, closing]`.
+#[derive(Clone, Debug)]
+pub struct Quotes {
+    pub primary: [String; 2],
+    pub secondary: [String; 2],
+}
+
+impl Quotes {
+    /// Quotation marks used in `lang` (a language tag, e.g. `fr` or `de-CH`), English ones if it's unknown.
+    pub fn for_lang(lang: &str) -> Self {
+        let lang = lang.to_lowercase();
+        let (primary, secondary) = match lang.as_str() {
+            "de-ch" | "fr-ch" | "it-ch" => (["«", "»"], ["‹", "›"]),
+            _ => match lang.split(['-', '_']).next().unwrap_or_default() {
+                "fr" | "es" | "it" | "pt" | "ca" | "el" => (["«", "»"], ["“", "”"]),
+                "de" | "cs" | "sk" | "lt" | "is" => (["„", "“"], ["‚", "‘"]),
+                "ru" | "uk" | "be" => (["«", "»"], ["„", "“"]),
+                "pl" | "hu" | "ro" | "bg" | "hr" => (["„", "”"], ["«", "»"]),
+                "nl" | "da" => (["„", "”"], ["‚", "’"]),
+                "sv" | "fi" => (["”", "”"], ["’", "’"]),
+                "ja" => (["「", "」"], ["『", "』"]),
+                _ => (["“", "”"], ["‘", "’"]),
+            },
+        };
+
+        Self {
+            primary: primary.map(String::from),
+            secondary: secondary.map(String::from),
+        }
+    }
+
+    /// Quotation marks for a page: the ones of its language (`quotes.lang` in the front matter or `[misc.quotes]`, `html_lang` otherwise), overridden by `[misc.quotes]` and then by the page's `quotes`.
+    pub fn new(misc: &MiscConfig, page: &PageConfig) -> Self {
+        let lang = page
+            .quotes
+            .as_ref()
+            .and_then(|quotes| quotes.lang.as_deref())
+            .or_else(|| misc.quotes.as_ref()?.lang.as_deref())
+            .or(misc.html_lang.as_deref())
+            .unwrap_or("en");
+
+        let mut quotes = Self::for_lang(lang);
+        for overrides in [&misc.quotes, &page.quotes].into_iter().flatten() {
+            if let Some(primary) = &overrides.primary {
+                quotes.primary = primary.clone();
+            }
+            if let Some(secondary) = &overrides.secondary {
+                quotes.secondary = secondary.clone();
+            }
+        }
+        quotes
+    }
+}
+
+/// Smart punctuation: quotation marks (see [`Quotes`]), apostrophes, dashes and ellipses.
+pub struct Typography;
+
+impl Preprocessor for Typography {
+    fn name(&self) -> &str {
+        "typography"
+    }
+
+    fn run<'a>(
+        &self,
+        page: &PageContext<'_>,
+        front_matter: &mut PageConfig,
+        events: Vec<Event<'a>>,
+    ) -> Result<Vec<Event<'a>>> {
+        let quotes = Quotes::new(&page.config.misc, front_matter);
+        // A secondary quote was opened, the next `'` that isn't opening closes it (otherwise it's an apostrophe).
+        let mut in_secondary = false;
+
+        Ok(map_text(events, |rest, prev| {
+            // A quote opens if it's at the start of a word
+            let is_opening = prev.is_none_or(|prev| {
+                prev.is_whitespace()
+                    || "([{—–-/".contains(prev)
+                    || quotes.primary[0].ends_with(prev)
+                    || quotes.secondary[0].ends_with(prev)
+            });
+            if prev.is_none() {
+                in_secondary = false;
+            }
+
+            let replacement = if rest.starts_with("...") {
+                ("…", 3)
+            } else if rest.starts_with("---") {
+                ("—", 3)
+            } else if rest.starts_with("--") {
+                ("–", 2)
+            } else if rest.starts_with('"') {
+                (&*quotes.primary[usize::from(!is_opening)], 1)
+            } else if let Some(after) = rest.strip_prefix('\'') {
+                if is_opening {
+                    in_secondary = true;
+                    (&*quotes.secondary[0], 1)
+                } else if in_secondary && !after.starts_with(char::is_alphanumeric) {
+                    in_secondary = false;
+                    (&*quotes.secondary[1], 1)
+                } else {
+                    ("’", 1)
+                }
+            } else {
+                return None;
+            };
+            Some((replacement.0.to_string(), replacement.1))
+        }))
+    }
+}
+
+/// Emojicodes (`:cat:`) to emojis (🐱)
+pub struct Emojis;
+
+impl Preprocessor for Emojis {
+    fn name(&self) -> &str {
+        "emojis"
+    }
+
+    fn run<'a>(
+        &self,
+        _page: &PageContext<'_>,
+        _front_matter: &mut PageConfig,
+        events: Vec<Event<'a>>,
+    ) -> Result<Vec<Event<'a>>> {
+        Ok(map_text(events, |rest, prev| {
+            // `a:b:c` isn't an emojicode
+            if prev.is_some_and(char::is_alphanumeric) {
+                return None;
+            }
+            emoji(rest).map(|(emoji, len)| (emoji.to_string(), len))
+        }))
+    }
+}
+
+/// Applies `rule` to the text of `events`, outside of code blocks.
+///
+/// Adjacent text events are merged first, so a quote split by the parser is seen as a whole. `rule` gets the rest of the text and the previous character (`None` at the start of a block), it returns a replacement and how many bytes it replaces (`None` keeps the next character).
+fn map_text<'a>(
+    events: Vec<Event<'a>>,
+    mut rule: impl FnMut(&str, Option<char>) -> Option<(String, usize)>,
+) -> Vec<Event<'a>> {
+    let mut result = Vec::with_capacity(events.len());
+    let mut state = TextState::default();
+    let mut in_code_block = false;
+    let mut events = events.into_iter().peekable();
+
+    while let Some(event) = events.next() {
+        match &event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(_) if !in_code_block => {
+                let Event::Text(first) = event else {
+                    unreachable!()
+                };
+                let mut text = first.into_string();
+                while let Some(Event::Text(next)) = events.peek() {
+                    text.push_str(next);
+                    events.next();
+                }
+                state.apply(&text, &mut rule, &mut result);
+                continue;
+            }
+            Event::Code(code) => state.prev = code.chars().last(),
+            // A Handlebars expression is a value, a quote after it closes.
+            Event::Html(html) if html.ends_with("}}") => state.prev = Some('}'),
+            Event::SoftBreak | Event::HardBreak => state.prev = Some('\n'),
+            // Inline formatting doesn't interrupt the text, `"*quoted*"` still has an opening and a closing quote.
+            Event::Start(
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..),
+            )
+            | Event::End(
+                Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(..) | Tag::Image(..),
+            )
+            | Event::Html(_) => {}
+            _ => state.prev = None,
+        }
+
+        result.push(event);
+    }
+
+    result
+}
+
+/// What [`map_text`] needs to know about the text before the current one
+#[derive(Default)]
+struct TextState {
+    /// Last character written, `None` at the start of a block
+    prev: Option<char>,
+}
+
+impl TextState {
+    /// Transforms `text`, pushing it as a text event.
+    fn apply<'a>(
+        &mut self,
+        text: &str,
+        rule: &mut impl FnMut(&str, Option<char>) -> Option<(String, usize)>,
+        events: &mut Vec<Event<'a>>,
+    ) {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(c) = rest.chars().next() {
+            let (replacement, len) =
+                rule(rest, self.prev).unwrap_or_else(|| (c.to_string(), c.len_utf8()));
+
+            result.push_str(&replacement);
+            self.prev = replacement.chars().last().or(self.prev);
+            rest = &rest[len..];
+        }
+
+        push(events, Event::Text, &mut result);
+    }
+}
+
+/// Pushes the text collected so far (if any) as an event.
+fn push<'a>(events: &mut Vec<Event<'a>>, event: fn(CowStr<'a>) -> Event<'a>, text: &mut String) {
+    if !text.is_empty() {
+        events.push(event(CowStr::Boxed(std::mem::take(text).into_boxed_str())));
+    }
+}
+
+/// Parses an emojicode (`:cat:`) at the start of `text`, returns the emoji and the emojicode's length.
+fn emoji(text: &str) -> Option<(&'static str, usize)> {
+    let shortcode = text.strip_prefix(':')?;
+    let end = shortcode.find(|c: char| !(c.is_ascii_alphanumeric() || "_+-".contains(c)))?;
+    if end == 0 || !shortcode[end..].starts_with(':') {
+        return None;
+    }
+
+    get_by_shortcode(&shortcode[..end]).map(|emoji| (emoji.as_str(), end + 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::path::Path;
+
+    use handlebars::Handlebars;
+    use pulldown_cmark::{html, Parser};
+
+    use super::*;
+    use crate::config::Config;
+    use crate::site::{markdown_options, templates_as_html};
+
+    /// Runs `preprocessor` on `markdown`, in a site whose `html_lang` is `lang`, returns the HTML.
+    fn render(preprocessor: &dyn Preprocessor, markdown: &str, lang: &str) -> String {
+        let config: Config =
+            toml::from_str(&format!("[config]\n\n[misc]\nhtml_lang = \"{lang}\"\n")).unwrap();
+        let registry = Handlebars::new();
+        let page = PageContext {
+            path: Path::new("index.md"),
+            config: &config,
+            registry: &registry,
+            warnings: RefCell::default(),
+        };
+        let mut front_matter: PageConfig = toml::from_str("title = \"Test\"").unwrap();
+
+        // Like pages, whose Handlebars expressions are raw HTML
+        let events = templates_as_html(Parser::new_ext(markdown, markdown_options()).collect());
+        let events = preprocessor.run(&page, &mut front_matter, events).unwrap();
+        let mut html = String::new();
+        html::push_html(&mut html, events.into_iter());
+        html
+    }
+
+    fn typography(markdown: &str) -> String {
+        render(&Typography, markdown, "en")
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            typography("\"Hello\", she said. \"Bye\""),
+            "<p>“Hello”, she said. “Bye”</p>\n"
+        );
+        // Inline formatting doesn't interrupt the text
+        assert_eq!(
+            typography("\"*quoted*\" and (\"[link](a.md)\")"),
+            "<p>“<em>quoted</em>” and (“<a href=\"a.md\">link</a>”)</p>\n"
+        );
+        // A new paragraph starts again
+        assert_eq!(typography("a\"\n\n\"b"), "<p>a”</p>\n<p>“b</p>\n");
+    }
+
+    #[test]
+    fn nested_quotes() {
+        assert_eq!(
+            typography("\"She said 'hi' to me.\""),
+            "<p>“She said ‘hi’ to me.”</p>\n"
+        );
+        assert_eq!(
+            typography("\"'Nested' at the start\" and \"at the 'end'\""),
+            "<p>“‘Nested’ at the start” and “at the ‘end’”</p>\n"
+        );
+    }
+
+    #[test]
+    fn apostrophes() {
+        assert_eq!(
+            typography("Don't, it's James' book."),
+            "<p>Don’t, it’s James’ book.</p>\n"
+        );
+        // The apostrophe inside a nested quote doesn't close it
+        assert_eq!(
+            typography("'It's mine,' he said."),
+            "<p>‘It’s mine,’ he said.</p>\n"
+        );
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        assert_eq!(
+            typography("`\"code\" -- 'x'` and \"`x`\""),
+            "<p><code>&quot;code&quot; -- 'x'</code> and “<code>x</code>”</p>\n"
+        );
+        assert_eq!(
+            typography("```\n\"don't\" -- ...\n```"),
+            "<pre><code>&quot;don't&quot; -- ...\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn quotes_for_lang() {
+        let marks = |lang| {
+            let quotes = Quotes::for_lang(lang);
+            (quotes.primary.concat(), quotes.secondary.concat())
+        };
+        let pair = |primary: &str, secondary: &str| (primary.to_string(), secondary.to_string());
+
+        assert_eq!(marks("en"), pair("“”", "‘’"));
+        assert_eq!(marks("fr"), pair("«»", "“”"));
+        assert_eq!(marks("de"), pair("„“", "‚‘"));
+        assert_eq!(marks("ja"), pair("「」", "『』"));
+        // Region and case
+        assert_eq!(marks("pt-BR"), pair("«»", "“”"));
+        assert_eq!(marks("PL_pl"), pair("„”", "«»"));
+        assert_eq!(marks("de-CH"), pair("«»", "‹›"));
+        // English ones for unknown languages
+        assert_eq!(marks("tlh"), pair("“”", "‘’"));
+        assert_eq!(marks(""), pair("“”", "‘’"));
+    }
+
+    #[test]
+    fn quotes_of_the_page_language() {
+        assert_eq!(
+            render(&Typography, "\"Il a dit 'oui'\"", "fr"),
+            "<p>«Il a dit “oui”»</p>\n"
+        );
+        assert_eq!(
+            render(&Typography, "\"Er sagte 'ja'\"", "de"),
+            "<p>„Er sagte ‚ja‘“</p>\n"
+        );
+    }
+
+    #[test]
+    fn emojis() {
+        assert_eq!(
+            render(&Emojis, ":cat: and :+1:, (:heart:)", "en"),
+            "<p>🐱 and 👍, (❤️)</p>\n"
+        );
+        assert_eq!(
+            render(&Emojis, "*:cat:*:dog:", "en"),
+            "<p><em>🐱</em>🐶</p>\n"
+        );
+    }
+
+    #[test]
+    fn unknown_emojis() {
+        // Unknown shortcodes, times and words separated by colons are kept
+        assert_eq!(
+            render(&Emojis, ":not_an_emoji: at 10:30: a:cat:b :: :cat", "en"),
+            "<p>:not_an_emoji: at 10:30: a:cat:b :: :cat</p>\n"
+        );
+    }
+
+    #[test]
+    fn emojis_in_code() {
+        assert_eq!(
+            render(&Emojis, "`:cat:` :cat:", "en"),
+            "<p><code>:cat:</code> 🐱</p>\n"
+        );
+        assert_eq!(
+            render(&Emojis, "```\n:cat:\n```", "en"),
+            "<pre><code>:cat:\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn dashes_and_ellipses() {
+        assert_eq!(
+            typography("1--2, a --- b... - c"),
+            "<p>1–2, a — b… - c</p>\n"
+        );
+    }
+
+    /// Replaces `ab` by `X`
+    fn ab(rest: &str, _prev: Option<char>) -> Option<(String, usize)> {
+        rest.starts_with("ab").then(|| ("X".to_string(), 2))
+    }
+
+    #[test]
+    fn text_is_merged() {
+        let events = vec![Event::Text("a".into()), Event::Text("b".into())];
+        assert_eq!(map_text(events, ab), vec![Event::Text("X".into())]);
+
+        // The parser splits text at brackets
+        let events = Parser::new_ext("[ab] a[b", markdown_options()).collect();
+        let events = map_text(events, ab);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], Event::Text("[X] a[b".into()));
+    }
+
+    #[test]
+    fn templates_are_left_alone() {
+        assert_eq!(
+            typography("\"{{ page \"title\" }}\" -- {{x \"a\"}}..."),
+            "<p>“{{ page \"title\" }}” – {{x \"a\"}}…</p>\n"
+        );
+        let events = vec![Event::Html("{{x}}".into()), Event::Text("ab".into())];
+        assert_eq!(
+            map_text(events, ab),
+            vec![Event::Html("{{x}}".into()), Event::Text("X".into())]
+        );
+    }
+
+    #[test]
+    fn previous_character() {
+        let mut prevs = Vec::new();
+        let events = Parser::new_ext(
+            "a *b* `c` d  \ne\nf\n\ng\n```\nh\n```\ni",
+            markdown_options(),
+        )
+        .collect();
+        map_text(events, |rest, prev| {
+            if rest.starts_with(char::is_alphabetic) {
+                prevs.push((rest.chars().next().unwrap(), prev));
+            }
+            None
+        });
+
+        assert_eq!(
+            prevs,
+            [
+                ('a', None),
+                // Across inline formatting
+                ('b', Some(' ')),
+                ('d', Some(' ')),
+                // Line breaks
+                ('e', Some('\n')),
+                ('f', Some('\n')),
+                // Blocks, code blocks are skipped
+                ('g', None),
+                ('i', None),
+            ]
+        );
+    }
+
+    #[test]
+    fn state_spans_events() {
+        let mut state = TextState::default();
+        let mut events = Vec::new();
+        let mut rule = |rest: &str, prev: Option<char>| {
+            (rest.starts_with('x') && prev == Some('a')).then(|| ("y".to_string(), 1))
+        };
+
+        state.apply("a", &mut rule, &mut events);
+        state.apply("xx", &mut rule, &mut events);
+        assert_eq!(state.prev, Some('x'));
+
+        assert_eq!(events, [Event::Text("a".into()), Event::Text("yx".into())]);
+    }
+}
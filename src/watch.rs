@@ -0,0 +1,271 @@
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use cuteness::Site;
+
+/// What has to be rebuilt after a batch of file system events.
+#[derive(Default)]
+struct Changes {
+    /// Templates, styles overriding the built-in ones or `cuteconfig.toml` changed, everything depends on them.
+    everything: bool,
+    /// `SUMMARY.toml` changed, every page's sidebar has to be re-rendered.
+    summary: bool,
+    /// Something in `src/styles` changed.
+    styles: bool,
+    /// Markdown files that were created, modified or deleted.
+    pages: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        !self.everything && !self.summary && !self.styles && self.pages.is_empty()
+    }
+
+    fn add(&mut self, path: &Path, site: &Site) {
+        // Pages including the file (`{{#include}}`) have to be re-rendered, whatever it is.
+        if let Some(file) = site
+            .included_files()
+            .into_iter()
+            .find(|file| is_same_file(&site.root().join(file), path, site.root()))
+        {
+            self.pages.extend(site.pages_including(file));
+        }
+
+        let path = relative(path, site.root());
+
+        if site.template_dirs().iter().any(|dir| {
+            path.starts_with(relative(dir, site.root()))
+                || dir
+                    .canonicalize()
+                    .is_ok_and(|dir| path.starts_with(relative(&dir, site.root())))
+        }) {
+            self.everything = true;
+            return;
+        }
+
+        if path == Path::new("cuteconfig.toml") {
+            self.everything = true;
+        } else if path == Path::new("SUMMARY.toml") {
+            self.summary = true;
+        } else if path.starts_with("src/styles") {
+            self.styles = true;
+        } else if path.starts_with("src") && path.extension().is_some_and(|ext| ext == "md") {
+            self.pages.insert(path);
+        }
+    }
+}
+
+/// Watches `src`, `SUMMARY.toml`, `cuteconfig.toml` and the template directories, re-rendering only what each change affects.
+///
+/// `on_rebuild` is called after every successful rebuild.
+pub fn watch(mut site: Site, mut on_rebuild: impl FnMut()) -> Result<()> {
+    let (tx, rx) = channel();
+    let mut watcher =
+        notify::recommended_watcher(tx).context("Couldn't create file system watcher")?;
+
+    // The root directory is watched non-recursively (instead of `cuteconfig.toml` and `SUMMARY.toml` directly) because editors usually replace files when saving them.
+    watcher
+        .watch(site.root(), RecursiveMode::NonRecursive)
+        .context("Couldn't watch the project's directory")?;
+    watcher
+        .watch(&site.root().join("src"), RecursiveMode::Recursive)
+        .context("Couldn't watch directory `src`")?;
+    for dir in site.template_dirs() {
+        if dir.exists() {
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .with_context(|| format!("Couldn't watch directory `{}`", dir.display()))?;
+        }
+    }
+
+    let mut include_dirs = BTreeSet::new();
+    watch_includes(&mut watcher, &site, &mut include_dirs);
+
+    println!("Watching for changes (Ctrl+C to stop)");
+
+    let mut changes = Changes::default();
+    loop {
+        // Editors usually fire a bunch of events per save, collect them until they calm down.
+        let event = if changes.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(Duration::from_millis(100))
+        };
+
+        match event {
+            Ok(Ok(event)) => {
+                if !matches!(event.kind, EventKind::Access(_)) {
+                    for path in &event.paths {
+                        changes.add(path, &site);
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("Watch error: {e}"),
+            Err(RecvTimeoutError::Timeout) => {
+                // A typo shouldn't stop the watcher, just report it and wait for the next change.
+                match rebuild(&mut site, &std::mem::take(&mut changes)) {
+                    Ok(()) => on_rebuild(),
+                    Err(e) => eprintln!("Build failed: {e:?}"),
+                }
+                watch_includes(&mut watcher, &site, &mut include_dirs);
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+fn rebuild(site: &mut Site, changes: &Changes) -> Result<()> {
+    if changes.everything {
+        site.reload()?;
+        site.build()?;
+        println!("Rebuilt everything");
+    } else {
+        if changes.summary {
+            site.reload_summary()?;
+            site.render_all()?;
+            site.write_routing()?;
+            println!("Rebuilt every page (`SUMMARY.toml` changed)");
+        } else if !changes.pages.is_empty() {
+            for page in &changes.pages {
+                if site.root().join(page).exists() {
+                    site.render_page(page)?;
+                    println!("Rebuilt `{}`", page.display());
+                } else {
+                    site.remove_page(page)?;
+                    println!("Removed `{}`", page.display());
+                }
+            }
+            site.write_routing()?;
+        }
+
+        if changes.styles {
+            site.compile_styles()?;
+            println!("Rebuilt styles");
+        }
+    }
+
+    report(site);
+    Ok(site.save_cache()?)
+}
+
+/// Prints the warnings and broken links of the pages rendered so far, like `cuteness build` does.
+pub fn report(site: &Site) {
+    for warning in site.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    for link in site.check_links() {
+        eprintln!("warning: {link}");
+    }
+}
+
+/// Watches the directories of the included files that aren't in `src` (e.g. examples), `watched` are the ones already watched.
+fn watch_includes(watcher: &mut impl Watcher, site: &Site, watched: &mut BTreeSet<PathBuf>) {
+    for file in site.included_files() {
+        let Some(dir) = site.root().join(file).parent().map(Path::to_path_buf) else {
+            continue;
+        };
+        if file.starts_with("src") || !watched.insert(dir.clone()) {
+            continue;
+        }
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            eprintln!("Couldn't watch directory `{}`: {e}", dir.display());
+        }
+    }
+}
+
+/// Whether `included` (joined to the root) and `path` (reported by the watcher) are the same file, even if it was deleted.
+fn is_same_file(included: &Path, path: &Path, root: &Path) -> bool {
+    match (included.canonicalize(), path.canonicalize()) {
+        (Ok(included), Ok(path)) => included == path,
+        _ => relative(included, root) == relative(path, root),
+    }
+}
+
+/// Makes a path reported by the watcher relative to the project's root.
+fn relative(path: &Path, root: &Path) -> PathBuf {
+    let path = root
+        .canonicalize()
+        .ok()
+        .and_then(|root| path.strip_prefix(root).ok())
+        .or_else(|| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn changed_paths() {
+        let root = std::env::temp_dir().join(format!("cuteness-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, content) in [
+            (
+                "cuteconfig.toml",
+                "[misc]\nhtml_lang = \"en\"\n\n[config]\n",
+            ),
+            ("SUMMARY.toml", "map = []\n"),
+            (
+                "src/a.md",
+                "---\ntitle: A\n---\n{{#include ../examples/a.txt}}\n",
+            ),
+            ("src/b.md", "---\ntitle: B\n---\nB\n"),
+            ("examples/a.txt", "Included\n"),
+            ("templates/page.html.hbs", "{{{content}}}"),
+        ] {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut site = Site::builder(&root).in_memory(true).load().unwrap();
+        site.render_all().unwrap();
+
+        let changes = |paths: &[&str]| {
+            let mut changes = Changes::default();
+            for path in paths {
+                changes.add(&root.join(path), &site);
+            }
+            changes
+        };
+
+        assert!(changes(&[]).is_empty());
+
+        let pages = changes(&["src/b.md", "src/new.md", "src/notes.txt"]);
+        assert!(!pages.everything && !pages.summary && !pages.styles);
+        assert_eq!(
+            pages.pages,
+            BTreeSet::from([PathBuf::from("src/b.md"), PathBuf::from("src/new.md")])
+        );
+
+        // Pages including the file are re-rendered, wherever it is
+        let included = changes(&["examples/a.txt"]);
+        assert!(!included.everything);
+        assert_eq!(included.pages, BTreeSet::from([PathBuf::from("src/a.md")]));
+
+        let summary = changes(&["SUMMARY.toml"]);
+        assert!(summary.summary && !summary.everything);
+
+        let styles = changes(&["src/styles/main.scss"]);
+        assert!(styles.styles && !styles.everything && styles.pages.is_empty());
+
+        for path in ["cuteconfig.toml", "templates/page.html.hbs"] {
+            assert!(changes(&[path]).everything, "{path}");
+        }
+
+        // Anything else in the project is ignored
+        assert!(changes(&["www/static/a.html", "README.md"]).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}